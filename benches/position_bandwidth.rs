@@ -0,0 +1,133 @@
+//! Bytes per player per second spent on positions, with two `f32` per update against the
+//! quantized and delta encoded format of `protocol::quantize`.
+//!
+//! Both formats are written with lightyear's `Writer` and the serialization functions the
+//! protocol registers: its default serde functions for the plain `f32`s and the delta, the
+//! custom ones for the full quantized value.
+//!
+//! Run with `cargo bench --bench position_bandwidth`. The format itself is tested in
+//! `protocol::quantize`.
+
+use bevy::math::Vec2;
+use lightyear::serde::{SerializationError, registry::SerializeFns, writer::Writer};
+use rust_cpp_game_jim25::protocol::{
+    TICK_DURATION,
+    components::{MOVE_SPEED, PlayerPosition, SPEED_BOOST_MULTIPLIER},
+    map::MapSettings,
+    quantize::{self, GRID, PositionDelta},
+};
+
+/// Interval of the server's `ReplicationSender`
+const SEND_INTERVAL: f32 = 0.1;
+const SECONDS: usize = 60;
+
+struct Scenario {
+    name: &'static str,
+    /// Velocity per tick at `time` seconds
+    velocity: fn(f32) -> Vec2,
+}
+
+const SCENARIOS: [Scenario; 5] = [
+    Scenario {
+        name: "idle",
+        velocity: |_| Vec2::ZERO,
+    },
+    Scenario {
+        name: "walk",
+        velocity: |_| Vec2::X * MOVE_SPEED,
+    },
+    Scenario {
+        name: "walk diagonal",
+        velocity: |_| Vec2::ONE * MOVE_SPEED,
+    },
+    Scenario {
+        name: "boosted",
+        velocity: |_| Vec2::X * MOVE_SPEED * SPEED_BOOST_MULTIPLIER,
+    },
+    Scenario {
+        name: "zigzag",
+        velocity: |time| {
+            let direction = if time.fract() < 0.5 {
+                Vec2::X
+            } else {
+                Vec2::NEG_X
+            };
+            (direction + Vec2::Y * (time * 3.0).sin().signum()) * MOVE_SPEED
+        },
+    },
+];
+
+/// Bytes lightyear's `Writer` holds after `write`
+fn written(write: impl FnOnce(&mut Writer) -> Result<(), SerializationError>) -> usize {
+    let mut writer = Writer::default();
+    write(&mut writer).expect("positions serialize");
+    writer.to_bytes().len()
+}
+
+/// Bytes sent over [`SECONDS`] with every update acked before the next one goes out. Both
+/// formats start with the full value.
+fn measure(scenario: &Scenario, map: &MapSettings) -> (usize, usize) {
+    let raw_fns = SerializeFns::<Vec2>::default();
+    let delta_fns = SerializeFns::<PositionDelta>::default();
+
+    let start = GRID.snap(map.bounds.min + 0.2 * map.bounds.size());
+    let mut position = start;
+    let mut acked = GRID.quantize(position);
+    let mut last_sent = position;
+    let mut raw = written(|writer| (raw_fns.serialize)(&position, writer));
+    let mut quantized =
+        written(|writer| quantize::serialize_position(&PlayerPosition(position), writer));
+
+    let ticks = (SECONDS as f32 / TICK_DURATION.as_secs_f32()) as usize;
+    let mut next_send = SEND_INTERVAL;
+    for tick in 0..ticks {
+        let time = tick as f32 * TICK_DURATION.as_secs_f32();
+        // simulated on the grid, like the server does
+        position = GRID.snap(position + (scenario.velocity)(time));
+        // start over inside the map, like a player turning at a wall
+        if !map.bounds.contains(position) {
+            position = start;
+        }
+        if time < next_send {
+            continue;
+        }
+        next_send += SEND_INTERVAL;
+
+        // unchanged components are not sent at all, in both formats
+        if position != last_sent {
+            raw += written(|writer| (raw_fns.serialize)(&position, writer));
+            last_sent = position;
+        }
+        let current = GRID.quantize(position);
+        if current != acked {
+            let delta = PositionDelta::between(acked, current);
+            quantized += written(|writer| (delta_fns.serialize)(&delta, writer));
+            acked = current;
+        }
+    }
+    (raw, quantized)
+}
+
+fn main() {
+    println!(
+        "{:<16} {:>12} {:>12} {:>8}",
+        "scenario", "f32 B/s", "packed B/s", "saved"
+    );
+    let map = MapSettings::default();
+    for scenario in &SCENARIOS {
+        let (raw, quantized) = measure(scenario, &map);
+        let per_second = |bytes: usize| bytes as f32 / SECONDS as f32;
+        let saved = if raw == 0 {
+            0.0
+        } else {
+            100.0 * (1.0 - quantized as f32 / raw as f32)
+        };
+        println!(
+            "{:<16} {:>12.1} {:>12.1} {:>7.0}%",
+            scenario.name,
+            per_second(raw),
+            per_second(quantized),
+            saved
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use rust_cpp_game_jim25::client_runner;
+use std::path::PathBuf;
+
+fn main() {
+    let mut replay = None;
+    let mut identity = PathBuf::from(client_runner::IDENTITY_FILE);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--replay", Some(path)) => replay = Some(PathBuf::from(path)),
+            // one file per player, so that several clients on one machine don't share a profile
+            ("--identity", Some(path)) => identity = PathBuf::from(path),
+            _ => {
+                eprintln!("usage: client [--identity <path>] [--replay <path>]");
+                std::process::exit(2);
+            }
+        }
+    }
+    match replay {
+        Some(path) => client_runner::init_replay(&path),
+        None => client_runner::init(client_runner::REMOTE_SERVER_ADDR, &identity),
+    }
+}
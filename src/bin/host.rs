@@ -0,0 +1,5 @@
+use rust_cpp_game_jim25::host_runner;
+
+fn main() {
+    host_runner::init();
+}
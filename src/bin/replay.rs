@@ -0,0 +1,45 @@
+use rust_cpp_game_jim25::protocol::{replay::Replay, version::protocol_id};
+use std::{fs::File, io::BufReader, process::ExitCode};
+
+/// Headless replay runner: re-simulates a recorded match and checks it reproduces the
+/// recorded positions bit for bit.
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: replay <file.replay>");
+        return ExitCode::FAILURE;
+    };
+
+    let replay = match File::open(&path).and_then(|file| Replay::read(&mut BufReader::new(file))) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Could not read {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "Replay of map '{}' recorded with protocol {} at {:?} per tick",
+        replay.header.map, replay.header.protocol_version, replay.header.tick_duration
+    );
+    if replay.header.protocol_version != protocol_id() {
+        println!(
+            "⚠️ Recorded with protocol {}, simulating with {}",
+            replay.header.protocol_version,
+            protocol_id()
+        );
+    }
+
+    match replay.verify() {
+        Ok(ticks) => {
+            println!("✅ {ticks} ticks re-simulated without desync");
+            ExitCode::SUCCESS
+        }
+        Err(desync) => {
+            eprintln!(
+                "❌ Desync at tick {} for player {}: recorded {} but simulated {}",
+                desync.tick, desync.player, desync.recorded, desync.simulated
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
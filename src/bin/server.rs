@@ -0,0 +1,5 @@
+use rust_cpp_game_jim25::server_runner;
+
+fn main() {
+    server_runner::init();
+}
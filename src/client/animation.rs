@@ -0,0 +1,123 @@
+use crate::protocol::{
+    characters::{AnimationTags, CharacterRegistry},
+    components::*,
+};
+use bevy::prelude::*;
+use bevy_aseprite_ultra::prelude::*;
+
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnimationState {
+    #[default]
+    Idle,
+    Walk,
+    Run,
+}
+
+impl AnimationState {
+    fn tag(self, tags: &AnimationTags) -> &str {
+        match self {
+            AnimationState::Idle => &tags.idle,
+            AnimationState::Walk => &tags.walk,
+            AnimationState::Run => &tags.run,
+        }
+    }
+}
+
+/// Tags of the character asset this entity is drawn with
+#[derive(Component, Clone, Debug)]
+pub struct CharacterTags(pub AnimationTags);
+
+/// Tint of a palette from the character manifest, white when it can't be parsed
+pub fn palette_color(palette: Option<&String>) -> Color {
+    palette
+        .and_then(|hex| Srgba::hex(hex).ok())
+        .unwrap_or(Srgba::WHITE)
+        .into()
+}
+
+/// Color a team tints its players with
+pub fn team_color(team: Team) -> Color {
+    match team {
+        Team::Red => Color::srgb(1.0, 0.35, 0.3),
+        Team::Blue => Color::srgb(0.3, 0.55, 1.0),
+    }
+}
+
+/// Swaps the aseprite file, tags and tint when the server accepted a new appearance or moved
+/// the player to another team
+pub fn apply_appearance(
+    mut players: Query<
+        (
+            &PlayerAppearance,
+            Option<&Team>,
+            &mut AseAnimation,
+            &mut CharacterTags,
+            &mut AnimationState,
+            &mut Sprite,
+        ),
+        Or<(Changed<PlayerAppearance>, Changed<Team>)>,
+    >,
+    registry: Res<CharacterRegistry>,
+    asset_server: Res<AssetServer>,
+) {
+    for (appearance, team, mut animation, mut tags, mut state, mut sprite) in &mut players {
+        let character = registry.get_or_default(appearance);
+        *state = AnimationState::Idle;
+        tags.0 = character.animations.clone();
+        *animation = AseAnimation {
+            animation: Animation::tag(&tags.0.idle),
+            aseprite: asset_server.load(&character.aseprite),
+        };
+        let palette = palette_color(character.palettes.get(appearance.palette as usize));
+        sprite.color = match team {
+            Some(team) => palette.mix(&team_color(*team), 0.5),
+            None => palette,
+        };
+    }
+}
+
+/// Picks the animation from the replicated velocity, so remote players animate like the
+/// local one, and faces the sprite towards the horizontal movement. Players faster than a
+/// walk, like those with a [`SpeedBoost`], run.
+pub fn animate_players(
+    mut players: Query<(
+        &PlayerVelocity,
+        &CharacterTags,
+        &mut AnimationState,
+        &mut AseAnimation,
+        &mut Sprite,
+    )>,
+) {
+    for (velocity, tags, mut current, mut animation, mut sprite) in &mut players {
+        let speed = velocity.x.abs().max(velocity.y.abs());
+        let state = if speed == 0.0 {
+            AnimationState::Idle
+        } else if speed > MOVE_SPEED {
+            AnimationState::Run
+        } else {
+            AnimationState::Walk
+        };
+        if *current != state {
+            *current = state;
+            animation.animation = Animation::tag(state.tag(&tags.0));
+        }
+
+        // keep the last facing while moving vertically or standing still
+        if velocity.x != 0.0 {
+            sprite.flip_x = velocity.x < 0.0;
+        }
+    }
+}
+
+/// Dead players disappear until the server respawns them
+pub fn hide_dead_players(
+    mut players: Query<(&LifeState, &mut Visibility), (Changed<LifeState>, With<CharacterTags>)>,
+) {
+    for (life, mut visibility) in &mut players {
+        visibility.set_if_neq(if life.is_alive() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+use crate::protocol::{components::Inputs, map::MapSettings};
+use bevy::prelude::*;
+use lightyear::prelude::input::native::InputMarker;
+
+/// How the game camera follows the local player
+#[derive(Resource, Clone, Debug)]
+pub struct CameraFollow {
+    /// Half size of the box around the camera centre in which the player moves without
+    /// moving the camera
+    pub deadzone: Vec2,
+    /// Exponential decay rate towards the player, higher values follow more tightly
+    pub smoothing: f32,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        Self {
+            deadzone: Vec2::new(24.0, 16.0),
+            smoothing: 8.0,
+        }
+    }
+}
+
+/// Smoothed camera position. The camera `Transform` holds the same position snapped to
+/// whole pixels of the low resolution target, otherwise the sprites shimmer while moving.
+#[derive(Component, Default, Debug)]
+pub struct CameraFocus(Vec2);
+
+pub fn follow_player(
+    settings: Res<CameraFollow>,
+    map: Res<MapSettings>,
+    time: Res<Time>,
+    player: Query<&Transform, (With<InputMarker<Inputs>>, Without<startups::GameCamera>)>,
+    camera: Single<(&Camera, &mut Transform, &mut CameraFocus), With<startups::GameCamera>>,
+) {
+    let Ok(player) = player.single() else {
+        return;
+    };
+    let (camera, mut transform, mut focus) = camera.into_inner();
+
+    // only chase the part of the offset that left the deadzone
+    let offset = player.translation.truncate() - focus.0;
+    let goal = focus.0 + offset - offset.clamp(-settings.deadzone, settings.deadzone);
+    focus
+        .0
+        .smooth_nudge(&goal, settings.smoothing, time.delta_secs());
+
+    // keep the view inside the map, or centred on it when the map is smaller than the view
+    if let Some(viewport) = camera.logical_viewport_size() {
+        let min = map.bounds.min + viewport / 2.0;
+        let max = map.bounds.max - viewport / 2.0;
+        let center = map.bounds.center();
+        focus.0.x = if min.x <= max.x {
+            focus.0.x.clamp(min.x, max.x)
+        } else {
+            center.x
+        };
+        focus.0.y = if min.y <= max.y {
+            focus.0.y.clamp(min.y, max.y)
+        } else {
+            center.y
+        };
+    }
+
+    transform.translation.x = focus.0.x.round();
+    transform.translation.y = focus.0.y.round();
+}
@@ -0,0 +1,91 @@
+use super::rollbacks::RollbackDiagnostics;
+use crate::protocol::conditioner::NetworkConditions;
+use bevy::prelude::*;
+
+/// How long the overlay flashes after a rollback
+const ROLLBACK_FLASH_SECONDS: f32 = 0.3;
+const OVERLAY_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const ROLLBACK_BACKGROUND: Color = Color::srgba(0.8, 0.1, 0.1, 0.8);
+
+/// Text panel with network diagnostics, toggled with `F3`
+#[derive(Component)]
+pub struct DebugOverlay;
+
+pub fn setup_debug_overlay(mut commands: Commands) {
+    commands.spawn((
+        DebugOverlay,
+        Text::default(),
+        TextFont::from_font_size(12.0),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.0),
+            bottom: Val::Px(8.0),
+            ..default()
+        },
+        BackgroundColor(OVERLAY_BACKGROUND),
+        Visibility::Hidden,
+    ));
+}
+
+/// `F3` toggles the overlay and `F4` cycles through the simulated network presets
+pub fn debug_commands(
+    mut overlay: Single<&mut Visibility, With<DebugOverlay>>,
+    mut conditions: ResMut<NetworkConditions>,
+    keypress: Res<ButtonInput<KeyCode>>,
+) {
+    if keypress.just_pressed(KeyCode::F3) {
+        overlay.toggle_visible_hidden();
+    }
+    if keypress.just_pressed(KeyCode::F4) {
+        let (name, next) = conditions.next_preset();
+        *conditions = next;
+        info!("Simulating a {name} network: {:?}", *conditions);
+    }
+}
+
+/// Shows the simulated network and the rollbacks, flashing red whenever one happens
+pub fn update_debug_overlay(
+    conditions: Res<NetworkConditions>,
+    diagnostics: Res<RollbackDiagnostics>,
+    overlay: Single<(&mut Text, &mut BackgroundColor), With<DebugOverlay>>,
+    time: Res<Time>,
+    mut flash: Local<Timer>,
+    mut shown_count: Local<u32>,
+) {
+    let (mut text, mut background) = overlay.into_inner();
+    flash.tick(time.delta());
+    let new_rollbacks = diagnostics.count != *shown_count;
+    if new_rollbacks {
+        *shown_count = diagnostics.count;
+        *flash = Timer::from_seconds(ROLLBACK_FLASH_SECONDS, TimerMode::Once);
+    }
+    background.0 = if flash.is_finished() {
+        OVERLAY_BACKGROUND
+    } else {
+        OVERLAY_BACKGROUND.mix(&ROLLBACK_BACKGROUND, flash.fraction_remaining())
+    };
+    if !conditions.is_changed() && !new_rollbacks {
+        return;
+    }
+
+    let preset = NetworkConditions::PRESETS
+        .iter()
+        .find(|(_, preset)| preset == &*conditions)
+        .map_or("custom", |(name, _)| name);
+    text.0 = format!(
+        "Network [F4] {preset}: {} ms ±{} ms, {:.0}% lost, {:.0}% duplicated\nRollbacks [F6 export] {}",
+        conditions.latency_ms,
+        conditions.jitter_ms,
+        conditions.loss * 100.0,
+        conditions.duplication * 100.0,
+        diagnostics.count
+    );
+    if let Some(last) = diagnostics.records.back() {
+        text.0 += &format!(
+            ", last to tick {} from {}: {:.2} px off",
+            last.tick,
+            last.current_tick,
+            last.error()
+        );
+    }
+}
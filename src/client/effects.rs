@@ -0,0 +1,42 @@
+use crate::protocol::messages::*;
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+use lightyear::prelude::client::Client;
+use lightyear::prelude::*;
+
+/// How long a hit flash stays on screen
+const HIT_EFFECT_SECONDS: f32 = 0.25;
+
+/// Short lived flash where a hit landed
+#[derive(Component)]
+pub struct HitEffect(Timer);
+
+pub fn receive_hits(
+    mut receiver: Single<&mut MessageReceiver<HitEvent>, With<Client>>,
+    mut commands: Commands,
+) {
+    for hit in receiver.receive() {
+        commands.spawn((
+            HitEffect(Timer::from_seconds(HIT_EFFECT_SECONDS, TimerMode::Once)),
+            Sprite::from_color(Color::srgb(1.0, 0.3, 0.2), Vec2::splat(10.0)),
+            Transform::from_translation(hit.position.extend(1.0)),
+            RenderLayers::layer(0),
+        ));
+    }
+}
+
+/// Grows and fades hit flashes, then despawns them
+pub fn fade_hit_effects(
+    mut effects: Query<(Entity, &mut HitEffect, &mut Sprite, &mut Transform)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, mut effect, mut sprite, mut transform) in &mut effects {
+        if effect.0.tick(time.delta()).is_finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let progress = effect.0.fraction();
+        sprite.color.set_alpha(1.0 - progress);
+        transform.scale = Vec3::splat(1.0 + progress);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::protocol::{messages::*, version::*};
+use bevy::prelude::*;
+use lightyear::prelude::client::Client;
+use lightyear::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Why the server turned us away, if it did
+#[derive(Resource, Default, Debug)]
+pub struct Rejection(pub Option<RejectReason>);
+
+/// The token the server issued for this player's profile, kept in a file. Without a file
+/// system (on the web) every session plays a new profile.
+#[derive(Resource, Debug)]
+pub struct Identity {
+    path: PathBuf,
+    token: Option<String>,
+}
+
+impl Identity {
+    pub fn load(path: &Path) -> Self {
+        let token = fs::read_to_string(path)
+            .ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+        Self {
+            path: path.to_path_buf(),
+            token,
+        }
+    }
+}
+
+/// Banner in the middle of the screen telling the player why they can't play
+#[derive(Component)]
+pub struct RejectionBanner;
+
+pub fn setup_rejection_banner(mut commands: Commands) {
+    commands.spawn((
+        RejectionBanner,
+        Text::default(),
+        TextFont::from_font_size(20.0),
+        TextLayout::new_with_justify(Justify::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(10.0),
+            right: Val::Percent(10.0),
+            top: Val::Percent(40.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.8)),
+        Visibility::Hidden,
+    ));
+}
+
+/// Introduces the client to the server as soon as the connection is up
+pub fn send_hello(
+    trigger: On<Add, Connected>,
+    mut sender: Query<&mut MessageSender<Hello>, With<Client>>,
+    identity: Option<Res<Identity>>,
+) {
+    if let Ok(mut sender) = sender.get_mut(trigger.entity) {
+        sender.send::<HandshakeChannel>(Hello {
+            game_version: GAME_VERSION.to_string(),
+            protocol_id: protocol_id(),
+            profile_token: identity.and_then(|identity| identity.token.clone()),
+        });
+    }
+}
+
+/// Keeps the token of the profile the server created for us, for the next session
+pub fn receive_profile(
+    mut receiver: Single<&mut MessageReceiver<ProfileIssued>, With<Client>>,
+    identity: Option<ResMut<Identity>>,
+) {
+    let Some(mut identity) = identity else {
+        return;
+    };
+    for ProfileIssued { token } in receiver.receive() {
+        if let Err(e) = fs::write(&identity.path, &token) {
+            warn!(
+                "Could not store the profile token in {}: {e}",
+                identity.path.display()
+            );
+        }
+        identity.token = Some(token);
+    }
+}
+
+pub fn receive_rejection(
+    mut receiver: Single<&mut MessageReceiver<Rejected>, With<Client>>,
+    mut rejection: ResMut<Rejection>,
+) {
+    for Rejected(reason) in receiver.receive() {
+        warn!("The server rejected us: {reason}");
+        rejection.0 = Some(reason);
+    }
+}
+
+pub fn show_rejection(
+    rejection: Res<Rejection>,
+    banner: Single<(&mut Text, &mut Visibility), With<RejectionBanner>>,
+) {
+    if !rejection.is_changed() {
+        return;
+    }
+    let (mut text, mut visibility) = banner.into_inner();
+    match &rejection.0 {
+        Some(reason) => {
+            text.0 = format!("{reason}\nThe server closed the connection.");
+            *visibility = Visibility::Visible;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
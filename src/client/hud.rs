@@ -0,0 +1,121 @@
+use super::matches::MatchClock;
+use crate::protocol::{components::*, matches::MatchState, messages::*, pickups::Inventory};
+use bevy::prelude::*;
+use lightyear::prelude::client::Client;
+use lightyear::prelude::{input::native::InputMarker, *};
+use std::collections::VecDeque;
+
+/// How long a kill stays in the feed
+const KILL_FEED_SECONDS: f32 = 5.0;
+/// Most kills shown at once
+const KILL_FEED_LINES: usize = 5;
+
+/// Recent kills, newest last
+#[derive(Resource, Default, Debug)]
+pub struct KillFeed {
+    entries: VecDeque<(String, Timer)>,
+}
+
+/// Text panel with the local player's health and the kill feed
+#[derive(Component)]
+pub struct HudPanel;
+
+pub fn setup_hud(mut commands: Commands) {
+    commands.spawn((
+        HudPanel,
+        Text::default(),
+        TextFont::from_font_size(14.0),
+        TextLayout::new_with_justify(Justify::Right),
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.0),
+            top: Val::Px(8.0),
+            ..default()
+        },
+    ));
+}
+
+pub fn receive_kills(
+    mut receiver: Single<&mut MessageReceiver<PlayerKilled>, With<Client>>,
+    local: Query<&PlayerId, With<InputMarker<Inputs>>>,
+    mut feed: ResMut<KillFeed>,
+) {
+    let local = local.single().ok().map(|id| id.0);
+    let name = |peer: PeerId| {
+        if Some(peer) == local {
+            "you".to_string()
+        } else {
+            format!("{peer:?}")
+        }
+    };
+    for kill in receiver.receive() {
+        let line = match kill.killer {
+            Some(killer) => format!("{} killed {}", name(killer), name(kill.victim)),
+            None => format!("{} died", name(kill.victim)),
+        };
+        feed.entries.push_back((
+            line,
+            Timer::from_seconds(KILL_FEED_SECONDS, TimerMode::Once),
+        ));
+        if feed.entries.len() > KILL_FEED_LINES {
+            feed.entries.pop_front();
+        }
+    }
+}
+
+pub fn expire_kills(mut feed: ResMut<KillFeed>, time: Res<Time>) {
+    let before = feed.entries.len();
+    let entries = &mut feed.bypass_change_detection().entries;
+    for (_, timer) in entries.iter_mut() {
+        timer.tick(time.delta());
+    }
+    entries.retain(|(_, timer)| !timer.is_finished());
+    if entries.len() != before {
+        feed.set_changed();
+    }
+}
+
+pub fn update_hud(
+    feed: Res<KillFeed>,
+    player: Query<(Ref<Health>, Ref<LifeState>), With<InputMarker<Inputs>>>,
+    inventories: Query<(Ref<Inventory>, Option<&ControlledBy>)>,
+    host: Query<(), With<HostClient>>,
+    match_state: Res<State<MatchState>>,
+    clock: Res<MatchClock>,
+    mut panel: Single<&mut Text, With<HudPanel>>,
+) {
+    let player = player.single().ok();
+    let player_changed = player
+        .as_ref()
+        .is_some_and(|(health, life)| health.is_changed() || life.is_changed());
+    // a client only receives its own inventory, but the host also sees the server's
+    let inventory = inventories
+        .iter()
+        .find(|(_, controlled_by)| controlled_by.is_none_or(|c| host.contains(c.owner)))
+        .map(|(inventory, _)| inventory);
+    let inventory_changed = inventory.as_ref().is_some_and(|i| i.is_changed());
+    let match_changed = match_state.is_changed() || clock.is_changed();
+    if !feed.is_changed() && !player_changed && !inventory_changed && !match_changed {
+        return;
+    }
+
+    let mut text = match match_state.get() {
+        MatchState::WaitingForPlayers => "Waiting for players\n".to_string(),
+        MatchState::Countdown => format!("Starting in {}\n", clock.0),
+        MatchState::InProgress => format!("{}:{:02} left\n", clock.0 / 60, clock.0 % 60),
+        MatchState::Results => "Round over\n".to_string(),
+    };
+    text += &match player {
+        Some((_, life)) if !life.is_alive() => "Respawning...\n".to_string(),
+        Some((health, _)) => format!("HP {}/{}\n", health.current, health.max),
+        None => String::new(),
+    };
+    if let Some(inventory) = inventory {
+        text += &format!("Coins {}\n", inventory.coins);
+    }
+    for (line, _) in &feed.entries {
+        text += line;
+        text.push('\n');
+    }
+    panel.0 = text;
+}
@@ -0,0 +1,162 @@
+use crate::protocol::{characters::CharacterRegistry, components::*, messages::*};
+use bevy::prelude::*;
+use lightyear::prelude::{input::native::InputMarker, *};
+
+/// Rooms known from the last [`RoomList`] sent by the server
+#[derive(Resource, Default, Debug)]
+pub struct Lobby {
+    pub rooms: Vec<RoomInfo>,
+    pub current: u32,
+}
+
+/// Text panel listing the rooms
+#[derive(Component)]
+pub struct LobbyPanel;
+
+pub fn setup_lobby_panel(mut commands: Commands) {
+    commands.spawn((
+        LobbyPanel,
+        Text::default(),
+        TextFont::from_font_size(14.0),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.0),
+            top: Val::Px(8.0),
+            ..default()
+        },
+    ));
+}
+
+/// Asks for the room list as soon as the client is connected
+pub fn request_room_list(
+    trigger: On<Add, Connected>,
+    mut sender: Query<&mut MessageSender<RoomCommand>, With<Client>>,
+) {
+    if let Ok(mut sender) = sender.get_mut(trigger.entity) {
+        sender.send::<LobbyChannel>(RoomCommand::List);
+    }
+}
+
+pub fn receive_room_list(
+    mut receiver: Single<&mut MessageReceiver<RoomList>, With<Client>>,
+    mut lobby: ResMut<Lobby>,
+) {
+    for list in receiver.receive() {
+        lobby.rooms = list.rooms;
+        lobby.current = list.current;
+    }
+}
+
+/// `L` toggles the panel, `N` opens a new room, `1`-`9` join the listed rooms and
+/// `Backspace` closes the current room if we created it
+pub fn lobby_commands(
+    mut sender: Single<&mut MessageSender<RoomCommand>, With<Client>>,
+    mut panel: Single<&mut Visibility, With<LobbyPanel>>,
+    keypress: Res<ButtonInput<KeyCode>>,
+    lobby: Res<Lobby>,
+) {
+    if keypress.just_pressed(KeyCode::KeyL) {
+        panel.toggle_visible_hidden();
+    }
+    if keypress.just_pressed(KeyCode::KeyN) {
+        sender.send::<LobbyChannel>(RoomCommand::Create {
+            name: format!("room {}", lobby.rooms.len() + 1),
+        });
+    }
+    if keypress.just_pressed(KeyCode::Backspace) {
+        sender.send::<LobbyChannel>(RoomCommand::Close {
+            room: lobby.current,
+        });
+    }
+
+    const ROOM_KEYS: [KeyCode; 9] = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    for (key, room) in ROOM_KEYS.iter().zip(&lobby.rooms) {
+        if keypress.just_pressed(*key) && room.id != lobby.current {
+            sender.send::<LobbyChannel>(RoomCommand::Join { room: room.id });
+        }
+    }
+}
+
+/// `T` asks to switch to the other team, the server refuses during a round
+pub fn pick_team(
+    mut sender: Single<&mut MessageSender<SelectTeam>, With<Client>>,
+    player: Single<&Team, With<InputMarker<Inputs>>>,
+    keypress: Res<ButtonInput<KeyCode>>,
+) {
+    if keypress.just_pressed(KeyCode::KeyT) {
+        sender.send::<LobbyChannel>(SelectTeam(player.other()));
+    }
+}
+
+/// `C` cycles through the characters of the registry and `V` through the palettes of the
+/// current character. The server only applies picks that exist in its registry.
+pub fn pick_appearance(
+    mut sender: Single<&mut MessageSender<SelectAppearance>, With<Client>>,
+    player: Single<&PlayerAppearance, With<InputMarker<Inputs>>>,
+    keypress: Res<ButtonInput<KeyCode>>,
+    registry: Res<CharacterRegistry>,
+) {
+    let mut appearance = player.clone();
+    if keypress.just_pressed(KeyCode::KeyC) {
+        let next = registry
+            .characters
+            .iter()
+            .position(|character| character.id == appearance.character)
+            .map_or(0, |index| (index + 1) % registry.characters.len());
+        appearance = PlayerAppearance {
+            character: registry.characters[next].id.clone(),
+            palette: 0,
+        };
+    } else if keypress.just_pressed(KeyCode::KeyV) {
+        let palettes = registry.get_or_default(&appearance).palettes.len().max(1);
+        appearance.palette = ((appearance.palette as usize + 1) % palettes) as u8;
+    } else {
+        return;
+    }
+    sender.send::<LobbyChannel>(SelectAppearance(appearance));
+}
+
+pub fn update_lobby_panel(
+    lobby: Res<Lobby>,
+    player: Query<(Ref<PlayerAppearance>, Option<Ref<Team>>), With<InputMarker<Inputs>>>,
+    registry: Res<CharacterRegistry>,
+    mut panel: Single<&mut Text, With<LobbyPanel>>,
+) {
+    let (appearance, team) = player.single().ok().unzip();
+    let team = team.flatten();
+    if !lobby.is_changed()
+        && !appearance.as_ref().is_some_and(|a| a.is_changed())
+        && !team.as_ref().is_some_and(|t| t.is_changed())
+    {
+        return;
+    }
+
+    let mut text = "Rooms [L] hide [N] new [1-9] join [Backspace] close\n".to_string();
+    for (index, room) in lobby.rooms.iter().enumerate() {
+        let marker = if room.id == lobby.current { '>' } else { ' ' };
+        text += &format!("{marker} {}. {} ({})\n", index + 1, room.name, room.players);
+    }
+    if let Some(appearance) = appearance {
+        let character = registry.get_or_default(&appearance);
+        text += &format!(
+            "Character [C] {} palette [V] {}/{}\n",
+            character.name,
+            appearance.palette + 1,
+            character.palettes.len()
+        );
+    }
+    if let Some(team) = team {
+        text += &format!("Team [T] {:?}\n", *team);
+    }
+    panel.0 = text;
+}
@@ -0,0 +1,31 @@
+use crate::protocol::{components::RoomId, matches::*};
+use bevy::prelude::*;
+use lightyear::prelude::client::Client;
+
+/// Seconds left in the current [`MatchState`], 0 when it has no time limit
+#[derive(Resource, Default, Debug)]
+pub struct MatchClock(pub u16);
+
+/// Mirrors the replicated match of our room into the app state
+pub fn sync_match_state(
+    matches: Query<(&MatchStatus, Option<&RoomId>)>,
+    client: Single<Option<&RoomId>, With<Client>>,
+    state: Res<State<MatchState>>,
+    mut next_state: ResMut<NextState<MatchState>>,
+    mut clock: ResMut<MatchClock>,
+) {
+    // a client only receives the match of its room, but the host also sees every match of
+    // the server, which carry the room they belong to
+    let Some((status, _)) = matches
+        .iter()
+        .find(|(_, room)| room.is_none() || *room == *client)
+    else {
+        return;
+    };
+    if *state.get() != status.state {
+        next_state.set(status.state);
+    }
+    if clock.0 != status.remaining_secs {
+        clock.0 = status.remaining_secs;
+    }
+}
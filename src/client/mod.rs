@@ -1,4 +1,18 @@
+pub mod animation;
+pub mod camera;
+pub mod debug;
+pub mod effects;
+pub mod handshake;
+pub mod hud;
+pub mod lobby;
+pub mod matches;
 pub mod observers;
+pub mod pickups;
 pub mod plugin;
+pub mod projectiles;
+pub mod replay;
+pub mod rollbacks;
+pub mod scaling;
+pub mod scoreboard;
 pub mod startups;
 pub mod updates;
@@ -1,36 +1,89 @@
 use super::*;
-use crate::protocol::components::*;
+use crate::protocol::{characters::CharacterRegistry, components::*};
+use animation::{AnimationState, CharacterTags, palette_color};
 use bevy::{camera::visibility::RenderLayers, prelude::*};
 use bevy_aseprite_ultra::prelude::*;
 use lightyear::prelude::{input::native::InputMarker, *};
 
-/// When the predicted copy of the client-owned entity is spawned, do stuff
-/// - assign it a different saturation
-/// - keep track of it in the Global resource
+/// When a player entity is spawned on the client, do stuff
+/// - draw it with the idle animation of its character
+/// - give the predicted, locally-controlled one the `InputMarker`
 ///
 /// Note that this will be triggered multiple times: for the locally-controlled entity,
 /// but also for the remote-controlled entities that are spawned with [`Interpolated`].
-/// The `With<Predicted>` filter ensures we only add the `InputMarker` once.
+/// The `Predicted` check ensures we only add the `InputMarker` once.
 pub(crate) fn handle_predicted_spawn(
     trigger: On<Add, PlayerId>,
-    mut predicted: Query<(&PlayerPosition, &PlayerId), With<Predicted>>,
+    players: Query<
+        (
+            &PlayerPosition,
+            &PlayerId,
+            Option<&PlayerAppearance>,
+            Has<Predicted>,
+        ),
+        Or<(With<Predicted>, With<Interpolated>)>,
+    >,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    registry: Res<CharacterRegistry>,
 ) {
     info!("predicted spawn");
     let entity = trigger.entity;
-    if let Ok((pos, id)) = predicted.get_mut(entity) {
-        info!("Add InputMarker to Predicted entity: {:?}", entity);
-        commands.entity(entity).insert((
-            InputMarker::<Inputs>::default(),
-            Transform::from_xyz(pos.x, pos.y, 0.0),
-            RenderLayers::layer(0),
-            AseAnimation {
-                animation: Animation::tag("loop"),
-                aseprite: asset_server.load("elf.aseprite"),
-            },
-            Sprite::default(),
+    if let Ok((pos, id, appearance, is_predicted)) = players.get(entity) {
+        let appearance = appearance
+            .cloned()
+            .unwrap_or_else(|| registry.default_appearance());
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert((
+            player_sprite(pos, &appearance, &registry, &asset_server),
             id.to_owned(),
         ));
+        if is_predicted {
+            info!("Add InputMarker to Predicted entity: {:?}", entity);
+            entity_commands.insert(InputMarker::<Inputs>::default());
+        }
     }
 }
+
+/// NPCs are drawn like remote players. In host mode this also sees the server's NPCs, which
+/// live in the same world.
+pub(crate) fn handle_npc_spawn(
+    trigger: On<Add, Npc>,
+    npcs: Query<(&PlayerPosition, &PlayerAppearance)>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<CharacterRegistry>,
+) {
+    if let Ok((position, appearance)) = npcs.get(trigger.entity) {
+        commands.entity(trigger.entity).insert(player_sprite(
+            position,
+            appearance,
+            &registry,
+            &asset_server,
+        ));
+    }
+}
+
+/// Components that draw a player into the low resolution game layer
+pub(crate) fn player_sprite(
+    position: &PlayerPosition,
+    appearance: &PlayerAppearance,
+    registry: &CharacterRegistry,
+    asset_server: &AssetServer,
+) -> impl Bundle {
+    let character = registry.get_or_default(appearance);
+    (
+        Transform::from_xyz(position.x, position.y, 0.0),
+        RenderLayers::layer(0),
+        AseAnimation {
+            animation: Animation::tag(&character.animations.idle),
+            aseprite: asset_server.load(&character.aseprite),
+        },
+        Sprite {
+            color: palette_color(character.palettes.get(appearance.palette as usize)),
+            ..default()
+        },
+        AnimationState::default(),
+        CharacterTags(character.animations.clone()),
+    )
+}
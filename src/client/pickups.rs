@@ -0,0 +1,26 @@
+use crate::protocol::{components::*, pickups::*};
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+
+fn pickup_color(kind: PickupKind) -> Color {
+    match kind {
+        PickupKind::Health => Color::srgb(0.9, 0.2, 0.3),
+        PickupKind::SpeedBoost => Color::srgb(0.3, 0.6, 1.0),
+        PickupKind::Coin => Color::srgb(1.0, 0.8, 0.1),
+    }
+}
+
+/// Pickups don't move, so they are placed once when they get replicated
+pub fn draw_pickup(
+    trigger: On<Add, Pickup>,
+    pickups: Query<(&Pickup, &ObjectPosition)>,
+    mut commands: Commands,
+) {
+    let Ok((pickup, position)) = pickups.get(trigger.entity) else {
+        return;
+    };
+    commands.entity(trigger.entity).insert((
+        Sprite::from_color(pickup_color(pickup.0), Vec2::splat(6.0)),
+        Transform::from_translation(position.extend(-0.5)),
+        RenderLayers::layer(0),
+    ));
+}
@@ -1,4 +1,7 @@
 use super::*;
+use crate::protocol::{
+    conditioner::ConditionerPlugin, matches::MatchState, plugin::ProtocolPlugin,
+};
 use bevy::prelude::*;
 use bevy_aseprite_ultra::AsepriteUltraPlugin;
 use lightyear::prelude::client::input::*;
@@ -8,19 +11,119 @@ pub struct ClientPlugin;
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(AsepriteUltraPlugin);
-        app.add_plugins(crate::protocol::plugin::ProtocolPlugin);
+        // the host runs both plugins in one app, so only the first one registers the protocol
+        if !app.is_plugin_added::<ProtocolPlugin>() {
+            app.add_plugins(ProtocolPlugin);
+        }
+        if !app.is_plugin_added::<ConditionerPlugin>() {
+            app.add_plugins(ConditionerPlugin);
+        }
 
         app.add_systems(Startup, startups::setup_camera);
 
+        app.init_resource::<handshake::Rejection>();
+        app.add_observer(handshake::send_hello);
+        app.add_systems(Startup, handshake::setup_rejection_banner);
+        app.add_systems(
+            Update,
+            (
+                (handshake::receive_rejection, handshake::show_rejection).chain(),
+                handshake::receive_profile,
+            ),
+        );
+        app.add_plugins(scaling::ScalingPlugin);
+
         // app.add_systems(Update, updates::move_elf);
-        app.add_systems(Update, updates::sync_transform);
+        app.add_systems(
+            Update,
+            (updates::sync_transform, updates::sync_npc_transform),
+        );
+
+        app.init_resource::<camera::CameraFollow>();
+        app.add_systems(Update, camera::follow_player.after(updates::sync_transform));
 
         app.add_systems(
             FixedPreUpdate,
             // Inputs have to be buffered in the WriteClientInputs set
             updates::buffer_input.in_set(InputSystems::WriteClientInputs),
         );
-        app.add_systems(FixedUpdate, updates::player_movement);
+        app.add_systems(
+            FixedUpdate,
+            (
+                updates::player_movement,
+                projectiles::predict_projectiles,
+                projectiles::move_predicted_projectiles,
+            )
+                .chain(),
+        );
+        app.init_resource::<rollbacks::RollbackDiagnostics>();
+        app.add_systems(
+            FixedUpdate,
+            (
+                rollbacks::detect_rollbacks.before(updates::player_movement),
+                rollbacks::log_predictions.after(updates::player_movement),
+            ),
+        );
+        app.add_systems(Update, rollbacks::export_trace);
         app.add_observer(observers::handle_predicted_spawn);
+        app.add_observer(observers::handle_npc_spawn);
+
+        app.add_systems(Startup, projectiles::setup_walls);
+        app.add_observer(projectiles::draw_projectile);
+        app.add_observer(pickups::draw_pickup);
+        app.add_systems(Update, projectiles::sync_projectile_transform);
+
+        app.add_systems(
+            Update,
+            (
+                animation::apply_appearance,
+                animation::animate_players,
+                animation::hide_dead_players,
+            )
+                .chain(),
+        );
+        app.add_systems(
+            Update,
+            (effects::receive_hits, effects::fade_hit_effects).chain(),
+        );
+
+        app.init_state::<MatchState>();
+        app.init_resource::<matches::MatchClock>();
+        app.add_systems(PreUpdate, matches::sync_match_state);
+
+        app.init_resource::<hud::KillFeed>();
+        app.add_systems(Startup, hud::setup_hud);
+        app.add_systems(
+            Update,
+            (hud::receive_kills, hud::expire_kills, hud::update_hud).chain(),
+        );
+
+        app.add_systems(Startup, scoreboard::setup_scoreboard);
+        app.init_resource::<scoreboard::RoomStandings>();
+        app.add_systems(
+            Update,
+            (scoreboard::receive_standings, scoreboard::update_scoreboard).chain(),
+        );
+
+        app.add_systems(Startup, debug::setup_debug_overlay);
+        app.add_systems(
+            Update,
+            (debug::debug_commands, debug::update_debug_overlay).chain(),
+        );
+
+        app.init_resource::<lobby::Lobby>();
+        app.add_systems(Startup, lobby::setup_lobby_panel);
+        app.add_observer(lobby::request_room_list);
+        app.add_systems(
+            Update,
+            (
+                lobby::receive_room_list,
+                lobby::lobby_commands,
+                lobby::pick_appearance,
+                lobby::pick_team,
+                lobby::update_lobby_panel,
+            )
+                .chain(),
+        );
     }
 }
@@ -0,0 +1,79 @@
+use crate::protocol::{components::*, map::MapSettings, matches::MatchState, projectiles::*};
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+use lightyear::prelude::client::Client;
+use lightyear::prelude::input::native::ActionState;
+use lightyear::prelude::*;
+
+/// Spawns our own shots right away instead of waiting a round trip for the server's
+/// projectile. The server spawns the same [`PreSpawned`] hash, so lightyear matches the two.
+///
+/// Must run after `player_movement` like `fire_projectiles` runs after `movement`.
+pub fn predict_projectiles(
+    player: Query<(&PlayerId, &PlayerPosition, &LifeState, &ActionState<Inputs>), With<Predicted>>,
+    client: Single<&LocalTimeline, With<Client>>,
+    match_state: Res<State<MatchState>>,
+    mut last_tick: Local<Option<Tick>>,
+    mut commands: Commands,
+) {
+    let tick = client.tick();
+    // rollbacks run the ticks again, the shots of those ticks already exist
+    if last_tick.is_some_and(|last| tick - last <= 0) {
+        return;
+    }
+    *last_tick = Some(tick);
+
+    let Ok((id, position, life, inputs)) = player.single() else {
+        return;
+    };
+    let can_shoot = life.is_alive() && match_state.allows_input();
+    if let (Inputs::Shoot { aim, .. }, true) = (&inputs.0, can_shoot) {
+        commands.spawn(projectile(id.0, position.0, *aim, tick));
+    }
+}
+
+/// Simulates the projectiles we fired. Those of other players are interpolated.
+pub fn move_predicted_projectiles(
+    mut projectiles: Query<
+        (
+            Entity,
+            &mut ObjectPosition,
+            &Projectile,
+            &mut ProjectileLifetime,
+        ),
+        Or<(With<Predicted>, With<PreSpawned>)>,
+    >,
+    map: Res<MapSettings>,
+    mut commands: Commands,
+) {
+    for (entity, position, projectile, lifetime) in &mut projectiles {
+        if !shared_projectile_behaviour(position, projectile, lifetime, &map) {
+            commands.entity(entity).prediction_despawn();
+        }
+    }
+}
+
+pub fn draw_projectile(trigger: On<Add, Projectile>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert((
+        Sprite::from_color(Color::srgb(1.0, 0.9, 0.3), Vec2::splat(4.0)),
+        Transform::default(),
+        RenderLayers::layer(0),
+    ));
+}
+
+pub fn sync_projectile_transform(
+    mut projectiles: Query<(&ObjectPosition, &mut Transform), With<Projectile>>,
+) {
+    for (position, mut transform) in &mut projectiles {
+        transform.translation = position.extend(0.5);
+    }
+}
+
+pub fn setup_walls(mut commands: Commands, map: Res<MapSettings>) {
+    for wall in &map.walls {
+        commands.spawn((
+            Sprite::from_color(Color::srgb(0.3, 0.25, 0.2), wall.size()),
+            Transform::from_translation(wall.center().extend(-1.0)),
+            RenderLayers::layer(0),
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use super::*;
+use crate::protocol::{characters::CharacterRegistry, components::PlayerPosition, replay::*};
+use bevy::prelude::*;
+use bevy_aseprite_ultra::AsepriteUltraPlugin;
+
+/// Free camera speed in world units per second
+const CAMERA_SPEED: f32 = 200.0;
+/// How far the bracket keys scrub the timeline, in seconds
+const SCRUB_SECONDS: f32 = 1.0;
+
+/// Plays back a replay file recorded by the server, without any network connection.
+///
+/// Expects a [`ReplayPlayback`] resource to be inserted.
+///
+/// - `Space` pauses and resumes
+/// - `[` and `]` scrub the timeline, hold `Shift` to scrub ten times further
+/// - `,` and `.` step a single tick
+/// - `WASD` or the arrow keys move the camera
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(AsepriteUltraPlugin);
+        app.add_plugins(scaling::ScalingPlugin);
+        app.init_resource::<CharacterRegistry>();
+
+        app.add_systems(Startup, (startups::setup_camera, setup_timeline));
+        app.add_systems(
+            Update,
+            (control_playback, free_camera, apply_frame, update_timeline).chain(),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    frames: Vec<ReplayFrame>,
+    tick_seconds: f32,
+    map: String,
+    /// Current position in the timeline, in ticks
+    cursor: f32,
+    paused: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: &Replay) -> Self {
+        Self {
+            frames: replay.frames(),
+            tick_seconds: replay.header.tick_duration.as_secs_f32(),
+            map: replay.header.map.clone(),
+            cursor: 0.0,
+            paused: false,
+        }
+    }
+
+    fn last_tick(&self) -> f32 {
+        self.frames.len().saturating_sub(1) as f32
+    }
+}
+
+/// Sprite of a player in the replay, keyed by the recorded player id
+#[derive(Component)]
+pub struct ReplayActor(u64);
+
+#[derive(Component)]
+pub struct ReplayTimeline;
+
+fn setup_timeline(mut commands: Commands) {
+    commands.spawn((
+        ReplayTimeline,
+        Text::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.0),
+            bottom: Val::Px(8.0),
+            ..default()
+        },
+    ));
+}
+
+fn control_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    keypress: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    if keypress.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+
+    let scrub_ticks = SCRUB_SECONDS / playback.tick_seconds
+        * if keypress.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            10.0
+        } else {
+            1.0
+        };
+    if keypress.just_pressed(KeyCode::BracketLeft) {
+        playback.cursor -= scrub_ticks;
+    }
+    if keypress.just_pressed(KeyCode::BracketRight) {
+        playback.cursor += scrub_ticks;
+    }
+    if keypress.just_pressed(KeyCode::Comma) {
+        playback.paused = true;
+        playback.cursor = playback.cursor.floor() - 1.0;
+    }
+    if keypress.just_pressed(KeyCode::Period) {
+        playback.paused = true;
+        playback.cursor = playback.cursor.floor() + 1.0;
+    }
+
+    if !playback.paused {
+        playback.cursor += time.delta_secs() / playback.tick_seconds;
+    }
+    playback.cursor = playback.cursor.clamp(0.0, playback.last_tick());
+}
+
+fn free_camera(
+    mut camera: Single<&mut Transform, With<startups::GameCamera>>,
+    keypress: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    let mut direction = Vec2::ZERO;
+    if keypress.any_pressed([KeyCode::KeyW, KeyCode::ArrowUp]) {
+        direction.y += 1.0;
+    }
+    if keypress.any_pressed([KeyCode::KeyS, KeyCode::ArrowDown]) {
+        direction.y -= 1.0;
+    }
+    if keypress.any_pressed([KeyCode::KeyA, KeyCode::ArrowLeft]) {
+        direction.x -= 1.0;
+    }
+    if keypress.any_pressed([KeyCode::KeyD, KeyCode::ArrowRight]) {
+        direction.x += 1.0;
+    }
+    camera.translation += (direction * CAMERA_SPEED * time.delta_secs()).extend(0.0);
+}
+
+/// Moves the actors to the positions recorded for the current tick, spawning and despawning
+/// them as players join and leave
+fn apply_frame(
+    playback: Res<ReplayPlayback>,
+    mut actors: Query<(Entity, &ReplayActor, &mut Transform)>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<CharacterRegistry>,
+) {
+    let Some(frame) = playback.frames.get(playback.cursor as usize) else {
+        return;
+    };
+
+    for (entity, actor, mut transform) in &mut actors {
+        match frame
+            .positions
+            .iter()
+            .find(|(player, _)| *player == actor.0)
+        {
+            Some((_, position)) => {
+                transform.translation.x = position.x;
+                transform.translation.y = position.y;
+            }
+            None => commands.entity(entity).despawn(),
+        }
+    }
+
+    for (player, position) in &frame.positions {
+        if !actors.iter().any(|(_, actor, _)| actor.0 == *player) {
+            commands.spawn((
+                ReplayActor(*player),
+                observers::player_sprite(
+                    &PlayerPosition(*position),
+                    &registry.default_appearance(),
+                    &registry,
+                    &asset_server,
+                ),
+            ));
+        }
+    }
+}
+
+fn update_timeline(
+    playback: Res<ReplayPlayback>,
+    mut timeline: Single<&mut Text, With<ReplayTimeline>>,
+) {
+    let state = if playback.paused { "paused" } else { "playing" };
+    timeline.0 = format!(
+        "{} | {:.1}s / {:.1}s | {state}",
+        playback.map,
+        playback.cursor * playback.tick_seconds,
+        playback.last_tick() * playback.tick_seconds,
+    );
+}
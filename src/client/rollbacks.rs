@@ -0,0 +1,311 @@
+use crate::protocol::components::*;
+use bevy::{diagnostic::FrameCount, prelude::*};
+use lightyear::prelude::client::Client;
+use lightyear::prelude::input::native::ActionState;
+use lightyear::prelude::{LocalTimeline, PeerId, Predicted, PredictionManager};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Ticks of predictions kept to compare the server's corrections against
+const PREDICTION_LOG_TICKS: usize = 128;
+/// Rollbacks kept for the trace export
+const MAX_RECORDS: usize = 4096;
+/// Folder `F6` writes the traces to
+const TRACE_DIRECTORY: &str = "./traces";
+
+/// What a predicted entity did on one tick
+#[derive(Serialize, Clone, Debug)]
+pub struct PredictedTick {
+    pub tick: u16,
+    pub position: Vec2,
+    pub input: Inputs,
+}
+
+/// The last [`PREDICTION_LOG_TICKS`] ticks of a predicted entity, re-simulated ticks replace
+/// the ones they correct
+#[derive(Component, Default, Debug)]
+pub struct PredictionLog(VecDeque<PredictedTick>);
+
+impl PredictionLog {
+    fn record(&mut self, entry: PredictedTick) {
+        match self.0.iter_mut().find(|logged| logged.tick == entry.tick) {
+            Some(logged) => *logged = entry,
+            None => {
+                self.0.push_back(entry);
+                if self.0.len() > PREDICTION_LOG_TICKS {
+                    self.0.pop_front();
+                }
+            }
+        }
+    }
+
+    fn at(&self, tick: u16) -> Option<&PredictedTick> {
+        self.0.iter().find(|logged| logged.tick == tick)
+    }
+}
+
+/// One correction of the local prediction by the server
+#[derive(Serialize, Clone, Debug)]
+pub struct RollbackRecord {
+    /// Tick the server's state was for, the re-simulation starts there
+    pub tick: u16,
+    /// Latest tick the client had predicted when the correction arrived. The client's
+    /// [`LocalTimeline`] can't tell, it is already rewound to `tick` during the rollback.
+    pub current_tick: u16,
+    pub entity: String,
+    pub player: PeerId,
+    pub predicted: Vec2,
+    pub confirmed: Vec2,
+    /// Inputs the client applied from `tick` up to `current_tick`, replayed by the rollback
+    pub inputs: Vec<(u16, Inputs)>,
+}
+
+impl RollbackRecord {
+    pub fn error(&self) -> f32 {
+        self.predicted.distance(self.confirmed)
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct RollbackDiagnostics {
+    /// Rollbacks since the client started
+    pub count: u32,
+    pub records: VecDeque<RollbackRecord>,
+    /// Frame and start tick of the last rollback, every fixed update of a rollback runs in
+    /// the same frame
+    last_rollback: Option<(u32, u16)>,
+}
+
+impl RollbackDiagnostics {
+    /// Writes the records as `<name>.json` and `<name>.csv` into `directory`
+    pub fn export(&self, directory: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        let path = directory.join(format!("rollbacks-{}", since_epoch.as_millis()));
+
+        let records: Vec<_> = self.records.iter().collect();
+        fs::write(
+            path.with_extension("json"),
+            serde_json::to_string_pretty(&records)?,
+        )?;
+
+        let mut csv = fs::File::create(path.with_extension("csv"))?;
+        writeln!(
+            csv,
+            "tick,current_tick,entity,player,predicted_x,predicted_y,confirmed_x,confirmed_y,error,inputs"
+        )?;
+        for record in &self.records {
+            writeln!(
+                csv,
+                "{},{},{},{:?},{},{},{},{},{},\"{}\"",
+                record.tick,
+                record.current_tick,
+                record.entity,
+                record.player,
+                record.predicted.x,
+                record.predicted.y,
+                record.confirmed.x,
+                record.confirmed.y,
+                record.error(),
+                serde_json::to_string(&record.inputs)?.replace('"', "\"\"")
+            )?;
+        }
+        Ok(path)
+    }
+}
+
+/// Runs first in the fixed update. On the first tick of a rollback the predicted entities hold
+/// the server's state, which is compared to what we predicted for that tick. A rollback is new
+/// when its frame or start tick differs from the last one, so back to back rollbacks count even
+/// if no regular fixed update ran between them.
+pub fn detect_rollbacks(
+    manager: Single<&PredictionManager, With<Client>>,
+    predicted: Query<(Entity, &PlayerId, &PlayerPosition, &PredictionLog), With<Predicted>>,
+    frame: Res<FrameCount>,
+    mut diagnostics: ResMut<RollbackDiagnostics>,
+) {
+    if !manager.is_rollback() {
+        return;
+    }
+    let Some(tick) = manager.get_rollback_start_tick().map(|tick| tick.0) else {
+        return;
+    };
+    let rollback = Some((frame.0, tick));
+    if diagnostics.last_rollback == rollback {
+        return;
+    }
+    // only new rollbacks count as a change for the overlay
+    diagnostics.bypass_change_detection().last_rollback = rollback;
+
+    diagnostics.count += 1;
+    for (entity, id, confirmed, log) in &predicted {
+        let Some(predicted) = log.at(tick) else {
+            continue;
+        };
+        let record = RollbackRecord {
+            tick,
+            current_tick: log.0.back().map_or(tick, |latest| latest.tick),
+            entity: entity.to_string(),
+            player: id.0,
+            predicted: predicted.position,
+            confirmed: confirmed.0,
+            inputs: log
+                .0
+                .iter()
+                .skip_while(|logged| logged.tick != tick)
+                .map(|logged| (logged.tick, logged.input.clone()))
+                .collect(),
+        };
+        warn!(
+            "Rollback #{} of {entity:?} to tick {tick} at tick {}: predicted {} but the server had {} ({:.3} px off, {} inputs replayed)",
+            diagnostics.count,
+            record.current_tick,
+            record.predicted,
+            record.confirmed,
+            record.error(),
+            record.inputs.len()
+        );
+        diagnostics.records.push_back(record);
+        if diagnostics.records.len() > MAX_RECORDS {
+            diagnostics.records.pop_front();
+        }
+    }
+}
+
+/// Runs after the movement, also while re-simulating, so that the log holds the latest
+/// prediction of every tick
+pub fn log_predictions(
+    client: Single<&LocalTimeline, With<Client>>,
+    mut predicted: Query<
+        (
+            Entity,
+            &PlayerPosition,
+            &ActionState<Inputs>,
+            Option<&mut PredictionLog>,
+        ),
+        (With<Predicted>, With<PlayerId>),
+    >,
+    mut commands: Commands,
+) {
+    let tick = client.tick().0;
+    for (entity, position, input, log) in &mut predicted {
+        let entry = PredictedTick {
+            tick,
+            position: position.0,
+            input: input.0.clone(),
+        };
+        match log {
+            Some(mut log) => log.record(entry),
+            None => {
+                let mut log = PredictionLog::default();
+                log.record(entry);
+                commands.entity(entity).insert(log);
+            }
+        }
+    }
+}
+
+/// `F6` writes the recorded rollbacks to a JSON and a CSV trace
+pub fn export_trace(diagnostics: Res<RollbackDiagnostics>, keypress: Res<ButtonInput<KeyCode>>) {
+    if !keypress.just_pressed(KeyCode::F6) {
+        return;
+    }
+    match diagnostics.export(Path::new(TRACE_DIRECTORY)) {
+        Ok(path) => info!(
+            "Wrote {} rollbacks to {}.json and .csv",
+            diagnostics.records.len(),
+            path.display()
+        ),
+        Err(e) => error!("Could not write the rollback trace: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> RollbackRecord {
+        let direction = Direction {
+            up: true,
+            ..default()
+        };
+        RollbackRecord {
+            tick: 10,
+            current_tick: 11,
+            entity: "5v1".to_string(),
+            player: PeerId::Netcode(7),
+            predicted: Vec2::new(1.0, 2.0),
+            confirmed: Vec2::new(1.0, 5.0),
+            inputs: vec![
+                (10, Inputs::Direction(direction.clone())),
+                (
+                    11,
+                    Inputs::Attack {
+                        direction,
+                        view_tick: 4,
+                    },
+                ),
+            ],
+        }
+    }
+
+    fn export(name: &str) -> (PathBuf, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("rollbacks-{name}-{}", std::process::id()));
+        let mut diagnostics = RollbackDiagnostics::default();
+        diagnostics.records.push_back(record());
+        (diagnostics.export(&directory).unwrap(), directory)
+    }
+
+    #[test]
+    fn csv_quotes_the_inputs() {
+        let (path, directory) = export("csv");
+        let csv = fs::read_to_string(path.with_extension("csv")).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(",error,inputs"));
+
+        // the inputs are the last column, one quoted field with its quotes doubled
+        let (columns, field) = lines[1].split_at(lines[1].find('"').unwrap());
+        let columns: Vec<_> = columns.split(',').collect();
+        assert_eq!(columns.len(), 10);
+        assert_eq!(columns[..3], ["10", "11", "5v1"]);
+        assert_eq!(columns[4..9], ["1", "2", "1", "5", "3"]);
+        assert!(field.len() > 2 && field.ends_with('"'));
+        let inner = &field[1..field.len() - 1];
+        assert!(!inner.replace("\"\"", "").contains('"'));
+        let inputs: Vec<(u16, Inputs)> =
+            serde_json::from_str(&inner.replace("\"\"", "\"")).unwrap();
+        assert_eq!(inputs, record().inputs);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn json_lists_the_records() {
+        let (path, directory) = export("json");
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path.with_extension("json")).unwrap())
+                .unwrap();
+        let records = json.as_array().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record["tick"], 10);
+        assert_eq!(record["current_tick"], 11);
+        assert_eq!(record["entity"], "5v1");
+        assert_eq!(record["predicted"], serde_json::json!([1.0, 2.0]));
+        assert_eq!(record["confirmed"], serde_json::json!([1.0, 5.0]));
+        let inputs = record["inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[0][0], 10);
+        assert_eq!(inputs[1][1]["Attack"]["view_tick"], 4);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use super::*;
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowResized},
+};
+
+/// Size in pixels of the low resolution image the game world renders into
+pub const RENDER_SIZE: UVec2 = UVec2::new(480, 270);
+
+/// Shows the game image at the largest integer scale that fits the window, letterboxing the
+/// rest, and keeps [`CursorWorldPosition`] up to date.
+pub struct ScalingPlugin;
+
+impl Plugin for ScalingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderScale>();
+        app.init_resource::<CursorWorldPosition>();
+        app.add_systems(Update, (fit_to_window, cursor_to_world).chain());
+    }
+}
+
+/// Physical window pixels per game pixel
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct RenderScale(pub u32);
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+/// Position of the mouse cursor in the game world, `None` when it is outside the window
+/// or over the letterbox bars
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct CursorWorldPosition(pub Option<Vec2>);
+
+/// The sprite that shows the game image in the window
+#[derive(Component)]
+pub struct GameView;
+
+pub fn fit_to_window(
+    mut resized: MessageReader<WindowResized>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    view: Single<(&mut Transform, Ref<GameView>)>,
+    mut scale: ResMut<RenderScale>,
+) {
+    let (mut transform, game_view) = view.into_inner();
+    if resized.read().count() == 0 && !game_view.is_added() {
+        return;
+    }
+
+    // scale in physical pixels so that every game pixel covers the same number of screen pixels
+    let fits = window.physical_size() / RENDER_SIZE;
+    scale.0 = fits.x.min(fits.y).max(1);
+
+    // the window camera works in logical pixels
+    let logical_scale = scale.0 as f32 / window.scale_factor();
+    transform.scale = Vec3::new(logical_scale, logical_scale, 1.0);
+}
+
+pub fn cursor_to_world(
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<&Transform, With<startups::GameCamera>>,
+    scale: Res<RenderScale>,
+    mut cursor: ResMut<CursorWorldPosition>,
+) {
+    cursor.0 = window.physical_cursor_position().and_then(|position| {
+        let from_center = position - window.physical_size().as_vec2() / 2.0;
+        // window y grows downwards, world y upwards
+        let in_image = Vec2::new(from_center.x, -from_center.y) / scale.0 as f32;
+        let half_image = RENDER_SIZE.as_vec2() / 2.0;
+        in_image
+            .abs()
+            .cmple(half_image)
+            .all()
+            .then(|| camera.translation.truncate() + in_image)
+    });
+}
@@ -0,0 +1,68 @@
+use crate::protocol::stats::*;
+use bevy::prelude::*;
+use lightyear::prelude::client::Client;
+use lightyear::prelude::*;
+
+/// Latest standings of the room, as sent by the server
+#[derive(Resource, Default, Deref)]
+pub struct RoomStandings(Standings);
+
+/// Standings of the round, shown while `Tab` is held
+#[derive(Component)]
+pub struct ScoreboardPanel;
+
+pub fn setup_scoreboard(mut commands: Commands) {
+    commands.spawn((
+        ScoreboardPanel,
+        Text::default(),
+        TextFont::from_font_size(14.0),
+        BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        Node {
+            position_type: PositionType::Absolute,
+            align_self: AlignSelf::Center,
+            justify_self: JustifySelf::Center,
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+pub fn receive_standings(
+    mut receiver: Single<&mut MessageReceiver<Standings>, With<Client>>,
+    mut standings: ResMut<RoomStandings>,
+) {
+    if let Some(latest) = receiver.receive().last() {
+        standings.0 = latest;
+    }
+}
+
+pub fn update_scoreboard(
+    keypress: Res<ButtonInput<KeyCode>>,
+    standings: Res<RoomStandings>,
+    panel: Single<(&mut Text, &mut Visibility), With<ScoreboardPanel>>,
+) {
+    let (mut text, mut visibility) = panel.into_inner();
+    if !keypress.pressed(KeyCode::Tab) {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+
+    let mut board = format!(
+        "{:>3}  {:<24} {:>6} {:>6} {:>6} {:>6}\n",
+        "#", "player", "score", "kills", "deaths", "items"
+    );
+    for (rank, StandingsEntry { name, stats, .. }) in standings.entries.iter().enumerate() {
+        board += &format!(
+            "{:>3}  {:<24} {:>6} {:>6} {:>6} {:>6}\n",
+            rank + 1,
+            name,
+            stats.score,
+            stats.kills,
+            stats.deaths,
+            stats.pickups
+        );
+    }
+    text.0 = board;
+}
@@ -8,10 +8,14 @@ use bevy::{
 };
 use bevy_aseprite_ultra::prelude::*;
 
+/// The camera rendering the game world into the low resolution image
+#[derive(Component)]
+pub struct GameCamera;
+
 pub fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
     let size = Extent3d {
-        width: 1920 / 4,
-        height: 1080 / 4,
+        width: scaling::RENDER_SIZE.x,
+        height: scaling::RENDER_SIZE.y,
         ..default()
     };
 
@@ -44,14 +48,27 @@ pub fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
         },
         Msaa::Off,
         Camera2d,
+        GameCamera,
+        camera::CameraFocus::default(),
         Transform::default(),
         RenderLayers::layer(0),
     ));
 
+    // scaled to fit the window by `scaling::fit_to_window`
     commands.spawn((
         Sprite::from_image(target_handle),
+        scaling::GameView,
+        RenderLayers::layer(1),
+        Transform::default(),
+    ));
+    // everything around the game image is letterboxed
+    commands.spawn((
+        Camera2d,
+        Camera {
+            clear_color: ClearColorConfig::Custom(Color::BLACK),
+            ..default()
+        },
+        Msaa::Off,
         RenderLayers::layer(1),
-        Transform::default().with_scale(Vec3::splat(2.0)),
     ));
-    commands.spawn((Camera2d, Msaa::Off, RenderLayers::layer(1)));
 }
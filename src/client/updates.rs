@@ -1,9 +1,10 @@
 use super::*;
-use crate::protocol::components::*;
+use crate::protocol::{components::*, matches::MatchState, projectiles::SHOOT_COOLDOWN_TICKS};
 use bevy::prelude::*;
 use lightyear::prelude::client::input::*;
+use lightyear::prelude::client::{Client, InterpolationTimeline};
 use lightyear::prelude::input::native::*;
-use lightyear::prelude::{MessageReceiver, Predicted};
+use lightyear::prelude::{LocalTimeline, MessageReceiver, Predicted};
 
 pub(crate) fn sync_transform(
     sync_players: Query<(&PlayerPosition, &PlayerId)>,
@@ -21,17 +22,35 @@ pub(crate) fn sync_transform(
     }
 }
 
+pub(crate) fn sync_npc_transform(
+    mut npcs: Query<(&PlayerPosition, &mut Transform), (With<Npc>, Changed<PlayerPosition>)>,
+) {
+    for (position, mut transform) in &mut npcs {
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
 // System that reads from peripherals and adds inputs to the buffer
 /// This system must be run in the `InputSystemSet::BufferInputs` set in the `FixedPreUpdate` schedule
 /// to work correctly.
 ///
 /// I would also advise to use the `leafwing` feature to use the `LeafwingInputPlugin` instead of the
 /// `InputPlugin`, which contains more features.
+///
+/// `Space` swings: the swing carries the tick of the interpolated world we are looking at, so
+/// that the server checks the hit against where the other players were on our screen.
+/// Holding the left mouse button shoots towards the cursor whenever the shot cooldown allows.
 pub fn buffer_input(
-    mut query: Query<&mut ActionState<Inputs>, With<InputMarker<Inputs>>>,
+    mut query: Query<(&mut ActionState<Inputs>, &PlayerPosition), With<InputMarker<Inputs>>>,
+    timelines: Query<(&LocalTimeline, Option<&InterpolationTimeline>), With<Client>>,
     keypress: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Res<scaling::CursorWorldPosition>,
+    mut attack_held: Local<bool>,
+    mut shot_cooldown: Local<u16>,
 ) {
-    if let Ok(mut action_state) = query.single_mut() {
+    if let Ok((mut action_state, position)) = query.single_mut() {
         let mut direction = Direction {
             up: false,
             down: false,
@@ -50,17 +69,76 @@ pub fn buffer_input(
         if keypress.pressed(KeyCode::KeyD) || keypress.pressed(KeyCode::ArrowRight) {
             direction.right = true;
         }
+        // opposite keys cancel out, the server flags inputs holding both as impossible
+        if direction.up && direction.down {
+            direction.up = false;
+            direction.down = false;
+        }
+        if direction.left && direction.right {
+            direction.left = false;
+            direction.right = false;
+        }
+        // fixed ticks don't line up with frames, so track the key edge across ticks instead of
+        // relying on `just_pressed`
+        let attack = keypress.pressed(KeyCode::Space) && !*attack_held;
+        *attack_held = keypress.pressed(KeyCode::Space);
+        // only send the shot on ticks the server will accept it, holding the button would
+        // otherwise change the inputs every tick
+        *shot_cooldown = shot_cooldown.saturating_sub(1);
+        let aim = cursor
+            .0
+            .filter(|_| mouse.pressed(MouseButton::Left) && *shot_cooldown == 0)
+            .map(|target| target - position.0);
+
         // we always set the value. Setting it to None means that the input was missing, it's not the same
         // as saying that the input was 'no keys pressed'
-        action_state.0 = Inputs::Direction(direction);
+        action_state.0 = match timelines.single() {
+            // in host mode nothing is interpolated, we see the world at the current tick
+            Ok((local, interpolation)) if attack => Inputs::Attack {
+                direction,
+                view_tick: interpolation
+                    .map_or(local.tick(), |timeline| timeline.tick())
+                    .0,
+            },
+            _ => match aim {
+                Some(aim) => {
+                    *shot_cooldown = SHOOT_COOLDOWN_TICKS;
+                    Inputs::Shoot { direction, aim }
+                }
+                None => Inputs::Direction(direction),
+            },
+        };
     }
 }
 
 /// The client input only gets applied to predicted entities that we own
 /// This works because we only predict the user's controlled entity.
 /// If we were predicting more entities, we would have to only apply movement to the player owned one.
-pub fn player_movement(mut position_query: Query<(&mut PlayerPosition, &ActionState<Inputs>)>) {
-    for (position, input) in position_query.iter_mut() {
-        shared_movement_behaviour(position, input);
+///
+/// In host mode the local player is not predicted: the server moves it directly.
+pub fn player_movement(
+    mut position_query: Query<
+        (
+            &mut PlayerPosition,
+            &mut PlayerVelocity,
+            &ActionState<Inputs>,
+            &LifeState,
+            Option<&SpeedBoost>,
+        ),
+        With<Predicted>,
+    >,
+    client: Single<&LocalTimeline, With<Client>>,
+    match_state: Res<State<MatchState>>,
+) {
+    // the server ignores our inputs during the countdown and the results
+    if !match_state.allows_input() {
+        return;
+    }
+    let tick = client.tick().0;
+    for (position, velocity, input, life, boost) in position_query.iter_mut() {
+        if life.is_alive() {
+            let boosted = boost.is_some_and(|boost| boost.is_active(tick));
+            shared_movement_behaviour(position, velocity, input, boosted);
+        }
     }
 }
@@ -1,4 +1,7 @@
-use crate::client;
+use crate::{
+    client,
+    protocol::{TICK_DURATION, conditioner::NetworkConditions, replay::Replay},
+};
 use bevy::{
     ecs::{lifecycle::HookContext, world::DeferredWorld},
     log::{Level, LogPlugin},
@@ -14,13 +17,17 @@ use lightyear::{
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::{
-    io::Read,
+    fs::File,
+    io::{BufReader, Read},
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    str::FromStr,
-    time::Duration,
+    path::Path,
 };
 use {bevy::window::PresentMode, bevy::winit::WinitSettings};
 
+/// Address of the hosted jam server
+pub const REMOTE_SERVER_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(18, 133, 225, 101)), 5888);
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum ClientTransports {
@@ -103,10 +110,8 @@ pub(crate) fn connect(mut commands: Commands, client: Single<Entity, With<Client
     });
 }
 
-pub fn init() {
-    println!("init the client");
-
-    let mut app = App::new();
+/// Adds the bevy plugins needed to open the game window and render
+pub fn add_default_plugins(app: &mut App) {
     app.add_plugins(
         DefaultPlugins
             .build()
@@ -126,6 +131,8 @@ pub fn init() {
                         title: format!("{}", env!("CARGO_PKG_NAME")),
                         resolution: (1920/2, 1080/2).into(),
                         present_mode: PresentMode::AutoVsync,
+                        // follow the browser window size, `scaling` keeps the pixels crisp
+                        fit_canvas_to_parent: true,
                         // set to true if we want to capture tab etc in wasm
                         prevent_default_event_handling: true,
                         ..Default::default()
@@ -133,18 +140,56 @@ pub fn init() {
                     ..default()
                 })
     );
+}
+
+/// `identity` is the file the profile token is kept in, one per player on this machine
+pub fn init(server_addr: SocketAddr, identity: &Path) {
+    println!("init the client");
+
+    let mut app = App::new();
+    add_default_plugins(&mut app);
 
     app.add_plugins(lightyear::prelude::client::ClientPlugins {
-        tick_duration: Duration::from_secs_f64(1.0 / 60.0),
+        tick_duration: TICK_DURATION,
     });
     app.add_plugins(client::plugin::ClientPlugin);
+    app.insert_resource(NetworkConditions::load(Path::new(CLIENT_CONFIG)));
+    app.insert_resource(client::handshake::Identity::load(identity));
 
     // we want the same frequency of updates for both focused and unfocused
     // Otherwise when testing the movement can look choppy for unfocused windows
     app.insert_resource(WinitSettings::continuous());
 
-    let port = 5888;
+    app.world_mut().spawn(ExampleClient {
+        client_id: client_id(),
+        // any free port, so that a client can share a machine with the server
+        client_port: 0,
+        server_addr,
+        transport: ClientTransports::WebTransport,
+        shared: SharedSettings {
+            protocol_id: crate::protocol::version::NETCODE_PROTOCOL_ID,
+            private_key: [
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0,
+            ],
+        },
+    });
+
+    app.add_systems(Startup, connect);
+
+    app.run();
+}
+
+/// Optional local settings of the client, like `{"conditions": {"latency_ms": 100}}` to test
+/// against a slow network
+pub const CLIENT_CONFIG: &str = "./client.json";
+
+/// Default file the client keeps its profile token in, see [`client::handshake::Identity`]
+pub const IDENTITY_FILE: &str = "./identity";
 
+/// A new random id every session. It only tells the connections apart, the profile is found
+/// through the [`Identity`](client::handshake::Identity).
+fn client_id() -> u64 {
     let now = std::time::SystemTime::now();
     let since_epoch = now
         .duration_since(std::time::UNIX_EPOCH)
@@ -160,22 +205,27 @@ pub fn init() {
         u64::from_le_bytes(arr)
     }
 
-    app.world_mut().spawn(ExampleClient {
-        client_id: vec_to_u64_le(hasher.to_vec()),
-        client_port: port,
-        server_addr: SocketAddr::new(IpAddr::from_str("18.133.225.101").unwrap(), port),
-        //server_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
-        transport: ClientTransports::WebTransport,
-        shared: SharedSettings {
-            protocol_id: 0,
-            private_key: [
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0,
-            ],
-        },
-    });
+    vec_to_u64_le(hasher.to_vec())
+}
 
-    app.add_systems(Startup, connect);
+/// Plays back a replay file recorded by the server instead of connecting to it
+pub fn init_replay(path: &Path) {
+    println!("init the replay player");
+
+    let replay = match File::open(path).and_then(|file| Replay::read(&mut BufReader::new(file))) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Could not read replay {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    };
+
+    let mut app = App::new();
+    add_default_plugins(&mut app);
+
+    app.insert_resource(client::replay::ReplayPlayback::new(&replay));
+    app.add_plugins(client::replay::ReplayPlugin);
+    app.insert_resource(WinitSettings::continuous());
 
     app.run();
 }
@@ -0,0 +1,2 @@
+pub mod observers;
+pub mod plugin;
@@ -0,0 +1,31 @@
+use crate::client::observers::player_sprite;
+use crate::protocol::{characters::CharacterRegistry, components::*};
+use bevy::prelude::*;
+use lightyear::prelude::{input::native::InputMarker, *};
+
+/// In host mode the player entities spawned by `handle_connected` live in the same world
+/// as the local client, so no [`Predicted`] copy is ever created for them.
+///
+/// The player controlled by the [`HostClient`] link gets the `InputMarker`, which makes the
+/// client input systems write straight into the `ActionState` the server reads. Every
+/// other player only gets a sprite.
+pub(crate) fn handle_host_player_spawn(
+    trigger: On<Add, PlayerId>,
+    players: Query<(&PlayerPosition, &PlayerAppearance, &ControlledBy), Without<Predicted>>,
+    host_clients: Query<(), With<HostClient>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<CharacterRegistry>,
+) {
+    let entity = trigger.entity;
+    let Ok((pos, appearance, controlled_by)) = players.get(entity) else {
+        return;
+    };
+
+    let mut entity_commands = commands.entity(entity);
+    entity_commands.insert(player_sprite(pos, appearance, &registry, &asset_server));
+    if host_clients.contains(controlled_by.owner) {
+        info!("Add InputMarker to host player entity: {:?}", entity);
+        entity_commands.insert(InputMarker::<Inputs>::default());
+    }
+}
@@ -0,0 +1,13 @@
+use super::*;
+use bevy::prelude::*;
+
+/// Glue needed when the server and the local client share one app.
+///
+/// Must be added together with both `ServerPlugin` and `ClientPlugin`.
+pub struct HostPlugin;
+
+impl Plugin for HostPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(observers::handle_host_player_spawn);
+    }
+}
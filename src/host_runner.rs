@@ -0,0 +1,50 @@
+use crate::protocol::{TICK_DURATION, conditioner::NetworkConditions, random::GameRng};
+use crate::{client, client_runner, host, server, server_runner};
+use bevy::{prelude::*, winit::WinitSettings};
+use lightyear::prelude::{client::ClientPlugins, server::ServerPlugins, *};
+use std::path::Path;
+
+/// Listen server: hosts the WebTransport server and plays as a local client in the same app.
+///
+/// The local client is linked to the server directly, so its inputs never go through the
+/// network while remote clients can still join on [`server_runner::SERVER_PORT`].
+pub fn init() {
+    println!("init the host");
+
+    let mut app = App::new();
+    client_runner::add_default_plugins(&mut app);
+
+    // client and server share the same world and fixed schedule, so they must tick together
+    app.add_plugins(ClientPlugins {
+        tick_duration: TICK_DURATION,
+    });
+    app.add_plugins(ServerPlugins {
+        tick_duration: TICK_DURATION,
+    });
+
+    app.add_plugins(server::plugin::ServerPlugin);
+    app.add_plugins(client::plugin::ClientPlugin);
+    app.add_plugins(host::plugin::HostPlugin);
+    // the host client is linked directly, only the remote clients go through the conditioner
+    app.insert_resource(NetworkConditions::load(Path::new(
+        server_runner::SERVER_CONFIG,
+    )));
+    app.insert_resource(GameRng::load(Path::new(server_runner::SERVER_CONFIG)));
+
+    app.insert_resource(WinitSettings::continuous());
+
+    let server = server_runner::spawn_server(&mut app);
+    app.world_mut().spawn((
+        Client::default(),
+        LinkOf { server },
+        Name::from("HostClient"),
+    ));
+
+    // the server has to be listening before the host client links to it
+    app.add_systems(
+        Startup,
+        (server_runner::start, client_runner::connect).chain(),
+    );
+
+    app.run();
+}
@@ -0,0 +1,19 @@
+pub mod protocol;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "server")]
+pub mod server_runner;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "client")]
+pub mod client_runner;
+
+#[cfg(all(feature = "server", feature = "client"))]
+pub mod host;
+
+#[cfg(all(feature = "server", feature = "client"))]
+pub mod host_runner;
@@ -1,21 +0,0 @@
-#[cfg(feature = "server")]
-mod server_runner;
-
-#[cfg(feature = "server")]
-mod server;
-
-#[cfg(feature = "client")]
-mod client_runner;
-
-#[cfg(feature = "client")]
-mod client;
-
-mod protocol;
-
-fn main() {
-    #[cfg(feature = "server")]
-    server_runner::init();
-
-    #[cfg(feature = "client")]
-    client_runner::init();
-}
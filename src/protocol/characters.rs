@@ -0,0 +1,70 @@
+use super::components::PlayerAppearance;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Aseprite tags played for each movement state of a character
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AnimationTags {
+    pub idle: String,
+    pub walk: String,
+    pub run: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CharacterDefinition {
+    /// Stable id replicated in [`PlayerAppearance`]
+    pub id: String,
+    /// Name shown in the menu
+    pub name: String,
+    /// Path of the aseprite file, relative to the assets folder
+    pub aseprite: String,
+    pub animations: AnimationTags,
+    /// Tints the sprite can be drawn with, as hex colors
+    pub palettes: Vec<String>,
+}
+
+/// Every character a player may pick, loaded from `assets/characters.json`.
+///
+/// The manifest is embedded at build time so that the server, which has no asset loading,
+/// validates against exactly the characters the client can draw.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CharacterRegistry {
+    pub characters: Vec<CharacterDefinition>,
+}
+
+impl Default for CharacterRegistry {
+    fn default() -> Self {
+        let registry: Self = serde_json::from_str(include_str!("../../assets/characters.json"))
+            .expect("assets/characters.json is not a valid character manifest");
+        assert!(
+            !registry.characters.is_empty(),
+            "assets/characters.json has no characters"
+        );
+        registry
+    }
+}
+
+impl CharacterRegistry {
+    pub fn get(&self, id: &str) -> Option<&CharacterDefinition> {
+        self.characters.iter().find(|character| character.id == id)
+    }
+
+    /// The definition to draw `appearance` with, falling back to the first character
+    pub fn get_or_default(&self, appearance: &PlayerAppearance) -> &CharacterDefinition {
+        self.get(&appearance.character)
+            .unwrap_or(&self.characters[0])
+    }
+
+    pub fn default_appearance(&self) -> PlayerAppearance {
+        PlayerAppearance {
+            character: self.characters[0].id.clone(),
+            palette: 0,
+        }
+    }
+
+    /// Whether `appearance` names a known character and one of its palettes
+    pub fn is_valid(&self, appearance: &PlayerAppearance) -> bool {
+        self.get(&appearance.character)
+            .is_some_and(|character| (appearance.palette as usize) < character.palettes.len())
+    }
+}
@@ -1,3 +1,5 @@
+use super::quantize;
+use super::stats::PlayerStats;
 use bevy::{ecs::entity::MapEntities, prelude::*};
 use lightyear::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -7,6 +9,10 @@ use serde::{Deserialize, Serialize};
 pub(crate) struct PlayerBundle {
     id: PlayerId,
     position: PlayerPosition,
+    velocity: PlayerVelocity,
+    health: Health,
+    life: LifeState,
+    stats: PlayerStats,
 }
 
 impl PlayerBundle {
@@ -14,12 +20,70 @@ impl PlayerBundle {
         Self {
             id: PlayerId(id),
             position: PlayerPosition(position),
+            velocity: PlayerVelocity::default(),
+            health: Health::full(MAX_HEALTH),
+            life: LifeState::Alive,
+            stats: PlayerStats::default(),
         }
     }
 }
 
-#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct PlayerId(PeerId);
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct PlayerId(pub PeerId);
+
+/// A character driven by the server's AI instead of a client. It moves with the same
+/// [`PlayerPosition`] and [`PlayerVelocity`] as the players.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct Npc {
+    /// Id of the definition in `assets/npcs.json`
+    pub kind: String,
+}
+
+/// Character and palette a player is drawn with, validated by the server against the
+/// [`CharacterRegistry`](super::characters::CharacterRegistry)
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Reflect)]
+pub struct PlayerAppearance {
+    pub character: String,
+    pub palette: u8,
+}
+
+/// Match instance an entity or a client belongs to. Only entities in the same room interact
+/// and get replicated to each other.
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub struct RoomId(pub u32);
+
+/// Side a player plays for. Teams spawn at their own spawn points.
+#[derive(
+    Component,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Reflect,
+)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+
+    pub fn other(self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+}
 
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect, Deref, DerefMut)]
 pub struct PlayerPosition(pub Vec2);
@@ -32,6 +96,57 @@ impl Ease for PlayerPosition {
     }
 }
 
+/// Position of the things lying or flying around the map: projectiles and pickups. Kept apart
+/// from [`PlayerPosition`] so that queries over characters don't pick them up.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect, Deref, DerefMut)]
+pub struct ObjectPosition(pub Vec2);
+
+impl Ease for ObjectPosition {
+    fn interpolating_curve_unbounded(start: Self, end: Self) -> impl Curve<Self> {
+        FunctionCurve::new(Interval::UNIT, move |t| {
+            ObjectPosition(Vec2::lerp(start.0, end.0, t))
+        })
+    }
+}
+
+/// Movement of a player during the last tick, replicated so that every client can pick
+/// the right animation for remote players
+#[derive(
+    Component, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Reflect, Deref, DerefMut,
+)]
+pub struct PlayerVelocity(pub Vec2);
+
+/// Hit points a player spawns with
+pub const MAX_HEALTH: u32 = 100;
+
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn full(max: u32) -> Self {
+        Self { current: max, max }
+    }
+}
+
+/// Dead players ignore their inputs and can't be hit until the server respawns them
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Reflect,
+)]
+pub enum LifeState {
+    #[default]
+    Alive,
+    Dead,
+}
+
+impl LifeState {
+    pub fn is_alive(self) -> bool {
+        self == LifeState::Alive
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Reflect)]
 pub struct Direction {
     pub(crate) up: bool,
@@ -40,9 +155,52 @@ pub struct Direction {
     pub(crate) right: bool,
 }
 
+impl Direction {
+    /// Movement of one tick at `speed` along every pressed direction, opposing ones cancel
+    pub fn step(&self, speed: f32) -> Vec2 {
+        let mut step = Vec2::ZERO;
+        if self.up {
+            step.y += speed;
+        }
+        if self.down {
+            step.y -= speed;
+        }
+        if self.left {
+            step.x -= speed;
+        }
+        if self.right {
+            step.x += speed;
+        }
+        step
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Reflect)]
 pub enum Inputs {
     Direction(Direction),
+    /// Movement plus a melee swing started this tick
+    Attack {
+        direction: Direction,
+        /// Tick of the world the client was looking at when swinging, so that the server can
+        /// rewind the other players to where the attacker saw them
+        view_tick: u16,
+    },
+    /// Movement plus a shot fired this tick
+    Shoot {
+        direction: Direction,
+        /// Direction of the shot
+        aim: Vec2,
+    },
+}
+
+impl Inputs {
+    pub fn direction(&self) -> &Direction {
+        match self {
+            Inputs::Direction(direction) => direction,
+            Inputs::Attack { direction, .. } => direction,
+            Inputs::Shoot { direction, .. } => direction,
+        }
+    }
 }
 
 impl Default for Inputs {
@@ -55,19 +213,41 @@ impl MapEntities for Inputs {
     fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {}
 }
 
-pub fn shared_movement_behaviour(mut position: Mut<PlayerPosition>, input: &Inputs) {
-    const MOVE_SPEED: f32 = 0.4;
-    let Inputs::Direction(direction) = input;
-    if direction.up {
-        position.y += MOVE_SPEED;
-    }
-    if direction.down {
-        position.y -= MOVE_SPEED;
-    }
-    if direction.left {
-        position.x -= MOVE_SPEED;
+/// Distance a player walks along each pressed direction per tick
+pub const MOVE_SPEED: f32 = 0.4;
+/// Speed factor applied while a [`SpeedBoost`] is active
+pub const SPEED_BOOST_MULTIPLIER: f32 = 1.5;
+
+/// Temporary speed-up from a pickup. Predicted, so the owning client moves faster right away.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct SpeedBoost {
+    /// Tick the boost wears off at
+    pub until: u16,
+}
+
+impl SpeedBoost {
+    pub fn is_active(&self, tick: u16) -> bool {
+        self.until.wrapping_sub(tick) as i16 > 0
     }
-    if direction.right {
-        position.x += MOVE_SPEED;
+}
+
+pub fn shared_movement_behaviour(
+    mut position: Mut<PlayerPosition>,
+    mut velocity: Mut<PlayerVelocity>,
+    input: &Inputs,
+    boosted: bool,
+) {
+    let speed = if boosted {
+        MOVE_SPEED * SPEED_BOOST_MULTIPLIER
+    } else {
+        MOVE_SPEED
+    };
+    let step = input.direction().step(speed);
+
+    velocity.set_if_neq(PlayerVelocity(step));
+    // only touch the position when moving, so idle players are not replicated again. The
+    // server, the predicting client and replays all land on the grid the wire carries exactly.
+    if step != Vec2::ZERO {
+        position.0 = quantize::snap(position.0 + step);
     }
 }
@@ -0,0 +1,245 @@
+use super::random::GameRng;
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+/// Simulated network trouble applied to every packet a link receives. Adding it on both the
+/// client and the server degrades both directions.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct NetworkConditions {
+    /// Delay added to every packet
+    pub latency_ms: u32,
+    /// Random extra delay of up to this much, which also reorders packets
+    pub jitter_ms: u32,
+    /// Share of packets dropped, from 0 to 1
+    pub loss: f32,
+    /// Share of packets received twice, from 0 to 1
+    pub duplication: f32,
+}
+
+impl NetworkConditions {
+    /// Presets the debug overlay cycles through
+    pub const PRESETS: [(&'static str, NetworkConditions); 4] = [
+        (
+            "off",
+            NetworkConditions {
+                latency_ms: 0,
+                jitter_ms: 0,
+                loss: 0.0,
+                duplication: 0.0,
+            },
+        ),
+        (
+            "wifi",
+            NetworkConditions {
+                latency_ms: 30,
+                jitter_ms: 10,
+                loss: 0.01,
+                duplication: 0.0,
+            },
+        ),
+        (
+            "mobile",
+            NetworkConditions {
+                latency_ms: 120,
+                jitter_ms: 40,
+                loss: 0.03,
+                duplication: 0.01,
+            },
+        ),
+        (
+            "terrible",
+            NetworkConditions {
+                latency_ms: 250,
+                jitter_ms: 100,
+                loss: 0.1,
+                duplication: 0.05,
+            },
+        ),
+    ];
+
+    pub fn is_off(&self) -> bool {
+        *self == NetworkConditions::default()
+    }
+
+    /// Preset that follows `self` on the debug overlay. A custom config continues with the
+    /// first preset.
+    pub fn next_preset(&self) -> (&'static str, NetworkConditions) {
+        let next = Self::PRESETS
+            .iter()
+            .position(|(_, preset)| preset == self)
+            .map_or(0, |index| (index + 1) % Self::PRESETS.len());
+        Self::PRESETS[next]
+    }
+
+    /// Lightyear's conditioner for the latency, jitter and loss, none when they are all off.
+    /// It doesn't duplicate packets, [`duplicate_packets`] does that.
+    pub fn link_conditioner(&self) -> Option<RecvLinkConditioner> {
+        let config = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(self.latency_ms as u64),
+            incoming_jitter: Duration::from_millis(self.jitter_ms as u64),
+            incoming_loss: self.loss,
+        };
+        let off = self.latency_ms == 0 && self.jitter_ms == 0 && self.loss == 0.0;
+        (!off).then(|| RecvLinkConditioner::new(config))
+    }
+
+    /// The `conditions` of a JSON config file, none if the file doesn't exist
+    pub fn load(path: &Path) -> Self {
+        #[derive(Deserialize, Default)]
+        struct Config {
+            #[serde(default)]
+            conditions: NetworkConditions,
+        }
+
+        let Ok(json) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Config>(&json) {
+            Ok(config) => config.conditions,
+            Err(e) => {
+                error!("Ignoring the invalid config {}: {e}", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+pub struct ConditionerPlugin;
+
+impl Plugin for ConditionerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkConditions>();
+        app.init_resource::<GameRng>();
+        app.add_observer(condition_new_link);
+        app.add_systems(
+            PreUpdate,
+            (
+                condition_links.run_if(resource_changed::<NetworkConditions>),
+                duplicate_packets
+                    .after(LinkSystems::Receive)
+                    .before(TransportSystems::Receive),
+            ),
+        );
+    }
+}
+
+/// New links start with the current conditions
+pub fn condition_new_link(
+    trigger: On<Add, Link>,
+    mut links: Query<&mut Link>,
+    conditions: Res<NetworkConditions>,
+) {
+    if let Ok(mut link) = links.get_mut(trigger.entity) {
+        link.recv.conditioner = conditions.link_conditioner();
+    }
+}
+
+/// Swaps the conditioner of every link when the conditions change, packets already held back
+/// by the old one are let through
+pub fn condition_links(mut links: Query<&mut Link>, conditions: Res<NetworkConditions>) {
+    for mut link in &mut links {
+        link.recv.conditioner = conditions.link_conditioner();
+    }
+}
+
+/// Receives a share of the packets the conditioner let through twice
+pub fn duplicate_packets(
+    mut links: Query<&mut Link>,
+    conditions: Res<NetworkConditions>,
+    mut rng: ResMut<GameRng>,
+) {
+    if conditions.duplication == 0.0 {
+        return;
+    }
+    for mut link in &mut links {
+        let received: Vec<_> = link.recv.drain().collect();
+        for packet in received {
+            if rng.random::<f32>() < conditions.duplication {
+                link.recv.push_raw(packet.clone());
+            }
+            link.recv.push_raw(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_cycle() {
+        let mut conditions = NetworkConditions::default();
+        for (name, _) in NetworkConditions::PRESETS.iter().skip(1) {
+            let (next_name, next) = conditions.next_preset();
+            assert_eq!(next_name, *name);
+            conditions = next;
+        }
+        assert_eq!(
+            conditions.next_preset(),
+            ("off", NetworkConditions::default())
+        );
+    }
+
+    #[test]
+    fn custom_conditions_continue_with_the_first_preset() {
+        let custom = NetworkConditions {
+            latency_ms: 77,
+            ..default()
+        };
+        assert_eq!(custom.next_preset().0, "off");
+    }
+
+    #[test]
+    fn loads_the_conditions_of_a_config() {
+        let directory = std::env::temp_dir().join(format!("conditions-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let config = directory.join("full.json");
+        fs::write(
+            &config,
+            r#"{"seed": 1, "conditions": {"latency_ms": 100, "jitter_ms": 20, "loss": 0.02, "duplication": 0.01}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            NetworkConditions::load(&config),
+            NetworkConditions {
+                latency_ms: 100,
+                jitter_ms: 20,
+                loss: 0.02,
+                duplication: 0.01,
+            }
+        );
+
+        let partial = directory.join("partial.json");
+        fs::write(&partial, r#"{"conditions": {"loss": 0.5}}"#).unwrap();
+        assert_eq!(
+            NetworkConditions::load(&partial),
+            NetworkConditions {
+                loss: 0.5,
+                ..default()
+            }
+        );
+
+        let invalid = directory.join("invalid.json");
+        fs::write(&invalid, "{").unwrap();
+        assert!(NetworkConditions::load(&invalid).is_off());
+        assert!(NetworkConditions::load(&directory.join("missing.json")).is_off());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn only_trouble_gets_a_conditioner() {
+        assert!(NetworkConditions::default().link_conditioner().is_none());
+        let duplicating = NetworkConditions {
+            duplication: 0.5,
+            ..default()
+        };
+        assert!(duplicating.link_conditioner().is_none());
+        assert!(NetworkConditions::PRESETS[1].1.link_conditioner().is_some());
+    }
+}
@@ -0,0 +1,128 @@
+use super::components::Team;
+use super::pickups::{PickupKind, PickupSpawner};
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// Playable area of the default map
+pub const MAP_BOUNDS: Rect = Rect {
+    min: Vec2::new(-480.0, -270.0),
+    max: Vec2::new(480.0, 270.0),
+};
+
+/// Static description of the map being played, identical on the server and the clients
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct MapSettings {
+    /// Name written into replays and shown to players
+    pub name: String,
+    /// Playable area in world space. Has to lie within [`MAP_BOUNDS`], which positions are
+    /// quantized relative to, see [`quantize`](super::quantize).
+    pub bounds: Rect,
+    /// Where players enter the map and respawn
+    pub spawn_points: Vec<SpawnPoint>,
+    /// Obstacles that stop projectiles
+    pub walls: Vec<Rect>,
+    /// Pickup spawners, instanced in every room
+    pub pickups: Vec<PickupSpawner>,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            name: "meadow".to_string(),
+            bounds: MAP_BOUNDS,
+            spawn_points: vec![
+                SpawnPoint::team(-320.0, -160.0, Team::Red),
+                SpawnPoint::team(-320.0, 160.0, Team::Red),
+                SpawnPoint::team(320.0, -160.0, Team::Blue),
+                SpawnPoint::team(320.0, 160.0, Team::Blue),
+                SpawnPoint {
+                    position: Vec2::ZERO,
+                    team: None,
+                },
+            ],
+            walls: vec![
+                Rect::new(-200.0, -24.0, -136.0, 24.0),
+                Rect::new(136.0, -24.0, 200.0, 24.0),
+                Rect::new(-24.0, 96.0, 24.0, 176.0),
+                Rect::new(-24.0, -176.0, 24.0, -96.0),
+            ],
+            pickups: vec![
+                PickupSpawner {
+                    position: Vec2::new(-168.0, 64.0),
+                    kinds: vec![PickupKind::Health],
+                    respawn_after: Duration::from_secs(15),
+                },
+                PickupSpawner {
+                    position: Vec2::new(168.0, -64.0),
+                    kinds: vec![PickupKind::Health],
+                    respawn_after: Duration::from_secs(15),
+                },
+                PickupSpawner {
+                    position: Vec2::new(0.0, 48.0),
+                    kinds: vec![PickupKind::SpeedBoost],
+                    respawn_after: Duration::from_secs(20),
+                },
+                PickupSpawner {
+                    position: Vec2::new(-320.0, 0.0),
+                    kinds: vec![PickupKind::Coin, PickupKind::Health, PickupKind::SpeedBoost],
+                    respawn_after: Duration::from_secs(8),
+                },
+                PickupSpawner {
+                    position: Vec2::new(320.0, 0.0),
+                    kinds: vec![PickupKind::Coin, PickupKind::Health, PickupKind::SpeedBoost],
+                    respawn_after: Duration::from_secs(8),
+                },
+                PickupSpawner {
+                    position: Vec2::new(0.0, -48.0),
+                    kinds: vec![PickupKind::Coin],
+                    respawn_after: Duration::from_secs(5),
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnPoint {
+    pub position: Vec2,
+    /// Only players of this team spawn here, `None` for everyone
+    pub team: Option<Team>,
+}
+
+impl SpawnPoint {
+    fn team(x: f32, y: f32, team: Team) -> Self {
+        Self {
+            position: Vec2::new(x, y),
+            team: Some(team),
+        }
+    }
+}
+
+impl MapSettings {
+    /// Whether `point` is inside a wall or outside the playable area
+    pub fn blocks(&self, point: Vec2) -> bool {
+        !self.bounds.contains(point) || self.walls.iter().any(|wall| wall.contains(point))
+    }
+
+    /// The spawn point of `team` farthest away from every position in `occupied`
+    pub fn spawn_point(
+        &self,
+        team: Option<Team>,
+        occupied: impl Iterator<Item = Vec2> + Clone,
+    ) -> Vec2 {
+        self.spawn_points
+            .iter()
+            .filter(|point| point.team.is_none() || point.team == team)
+            .map(|point| point.position)
+            .max_by(|a, b| {
+                let nearest = |point: Vec2| {
+                    occupied
+                        .clone()
+                        .map(|other| other.distance_squared(point))
+                        .fold(f32::INFINITY, f32::min)
+                };
+                nearest(*a).total_cmp(&nearest(*b))
+            })
+            .unwrap_or(Vec2::ZERO)
+    }
+}
@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle of the match running in a room. The server drives it, clients mirror the state
+/// of their room into their app state.
+#[derive(
+    States, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect,
+)]
+pub enum MatchState {
+    #[default]
+    WaitingForPlayers,
+    Countdown,
+    InProgress,
+    Results,
+}
+
+impl MatchState {
+    /// Players can only move while waiting and during the round
+    pub fn allows_input(self) -> bool {
+        matches!(self, MatchState::WaitingForPlayers | MatchState::InProgress)
+    }
+}
+
+/// State of the match of a room, replicated to the clients in that room
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct MatchStatus {
+    pub state: MatchState,
+    /// Whole seconds left in the current state, 0 when it has no time limit
+    pub remaining_secs: u16,
+}
@@ -0,0 +1,93 @@
+use super::components::{PlayerAppearance, Team};
+use super::version::RejectReason;
+use bevy::prelude::*;
+use lightyear::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Reliable channel for the handshake. Registered before everything else, so that builds
+/// with different protocols still understand each other's handshake.
+pub struct HandshakeChannel;
+
+/// Reliable channel for lobby and match management messages
+pub struct LobbyChannel;
+
+/// Reliable channel for gameplay events the clients show effects for
+pub struct EventChannel;
+
+/// First message of every client, the server checks it before letting the client play
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct Hello {
+    /// [`GAME_VERSION`](super::version::GAME_VERSION) of the client
+    pub game_version: String,
+    /// [`protocol_id`](super::version::protocol_id) of the client
+    pub protocol_id: u64,
+    /// Secret the server issued for the client's profile, see [`ProfileIssued`]. `None` on
+    /// the first visit.
+    pub profile_token: Option<String>,
+}
+
+/// The secret a client sends in its next [`Hello`] to get its profile back
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct ProfileIssued {
+    pub token: String,
+}
+
+/// Sent right before the server disconnects a client it won't serve
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct Rejected(pub RejectReason);
+
+/// Lobby requests sent by clients
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub enum RoomCommand {
+    List,
+    Create {
+        name: String,
+    },
+    Join {
+        room: u32,
+    },
+    /// Only the client that created the room may close it
+    Close {
+        room: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct RoomInfo {
+    pub id: u32,
+    pub name: String,
+    pub players: usize,
+}
+
+/// Sent to a client whenever the rooms change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct RoomList {
+    pub rooms: Vec<RoomInfo>,
+    /// The room the receiving client is in
+    pub current: u32,
+}
+
+/// Appearance picked in the menu, applied once the server validated it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct SelectAppearance(pub PlayerAppearance);
+
+/// Sent by a client to switch teams from the lobby
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct SelectTeam(pub Team);
+
+/// Broadcast to the room when a melee swing connects
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct HitEvent {
+    pub attacker: PeerId,
+    pub target: PeerId,
+    /// Where the target was when it got hit, as seen by the attacker
+    pub position: Vec2,
+}
+
+/// Broadcast to the room when a player dies, for the kill feed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct PlayerKilled {
+    pub victim: PeerId,
+    /// `None` when the player didn't die to another player
+    pub killer: Option<PeerId>,
+}
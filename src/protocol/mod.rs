@@ -1,2 +1,20 @@
+use std::time::Duration;
+
+pub mod characters;
 pub mod components;
+pub mod conditioner;
+pub mod map;
+pub mod matches;
+pub mod messages;
+pub mod pickups;
 pub mod plugin;
+pub mod projectiles;
+pub mod quantize;
+pub mod random;
+pub mod replay;
+pub mod stats;
+pub mod version;
+
+/// Length of one fixed tick, the same for the server and every client. Tick counts like
+/// cooldowns, boosts and melee rewinds only mean the same on both ends with the same tick.
+pub const TICK_DURATION: Duration = Duration::from_nanos(15_625_000);
@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum PickupKind {
+    Health,
+    SpeedBoost,
+    Coin,
+}
+
+/// An item lying on the map until a player walks over it. Its
+/// [`ObjectPosition`](super::components::ObjectPosition) puts it under the interest radius.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct Pickup(pub PickupKind);
+
+/// What a player carries. Only replicated to the client that owns it.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Reflect)]
+pub struct Inventory {
+    pub coins: u32,
+}
+
+/// Place on the map where pickups appear
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickupSpawner {
+    pub position: Vec2,
+    /// The spawner picks one of these at random every time it spawns
+    pub kinds: Vec<PickupKind>,
+    /// Delay between a collection and the next spawn
+    pub respawn_after: Duration,
+}
@@ -1,13 +1,128 @@
 use super::*;
 use bevy::prelude::*;
+use bevy::reflect::{TypeInfo, Typed};
 use lightyear::prelude::*;
+use lightyear::serde::registry::SerializeFns;
+
+/// Lists everything that goes over the wire once, in registration order: the plugin registers
+/// the list and [`version::protocol_id`] hashes the same list, so the two can't drift apart.
+///
+/// Entries are `channel Type, settings, direction;`, `message Type, direction;`,
+/// `input Type;`, `component Type [, serialize, deserialize] [=> add_prediction()...];` and
+/// `nested Type;` for types only sent inside a collection, whose layout the hash can't reach.
+macro_rules! wire_protocol {
+    ($($kind:ident $ty:ty $(, $setting:expr)* $(=> $extra:ident())*;)*) => {
+        fn register_wire_types(app: &mut App) {
+            $(wire_protocol!(@register app, $kind $ty $(, $setting)* $(=> $extra())*);)*
+        }
+
+        /// Everything [`ProtocolPlugin`] registers, in order
+        pub fn wire_types() -> Vec<WireType> {
+            vec![$(WireType {
+                kind: stringify!($kind),
+                name: stringify!($ty),
+                info: wire_protocol!(@info $kind $ty),
+                settings: wire_protocol!(@settings $kind $(, $setting)*)
+                    + stringify!($($extra)*),
+            }),*]
+        }
+    };
+
+    (@register $app:ident, channel $ty:ty, $settings:expr, $direction:expr) => {
+        $app.add_channel::<$ty>($settings).add_direction($direction);
+    };
+    (@register $app:ident, message $ty:ty, $direction:expr) => {
+        $app.register_message::<$ty>().add_direction($direction);
+    };
+    (@register $app:ident, input $ty:ty) => {
+        $app.add_plugins(input::native::InputPlugin::<$ty>::default());
+    };
+    (@register $app:ident, component $ty:ty $(=> $extra:ident())*) => {
+        $app.register_component::<$ty>()$(.$extra())*;
+    };
+    (@register $app:ident, component $ty:ty, $serialize:expr, $deserialize:expr $(=> $extra:ident())*) => {
+        $app.register_component_custom_serde::<$ty>(SerializeFns {
+            serialize: $serialize,
+            deserialize: $deserialize,
+        })$(.$extra())*;
+    };
+    (@register $app:ident, nested $ty:ty) => {};
+
+    // channels are markers, only their settings matter
+    (@info channel $ty:ty) => { None };
+    (@info $kind:ident $ty:ty) => { Some(<$ty as Typed>::type_info()) };
+
+    (@settings channel, $settings:expr, $direction:expr) => {
+        format!("{:?} {:?}", $settings, $direction)
+    };
+    (@settings message, $direction:expr) => { format!("{:?}", $direction) };
+    (@settings $kind:ident $(, $setting:expr)*) => { stringify!($($setting),*).to_string() };
+}
+
+/// One registration of [`wire_types`]
+pub struct WireType {
+    pub kind: &'static str,
+    /// The type as written in the registration
+    pub name: &'static str,
+    /// Field layout, none for channels
+    pub info: Option<&'static TypeInfo>,
+    /// What the registration configures besides the type: a channel's mode, a message's
+    /// direction, a component's serialization and prediction
+    pub settings: String,
+}
+
+fn reliable_channel() -> ChannelSettings {
+    ChannelSettings {
+        mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+        ..default()
+    }
+}
+
+wire_protocol! {
+    // first, see `messages::HandshakeChannel`
+    channel messages::HandshakeChannel, reliable_channel(), NetworkDirection::Bidirectional;
+    message messages::Hello, NetworkDirection::ClientToServer;
+    message messages::Rejected, NetworkDirection::ServerToClient;
+
+    input components::Inputs;
+    component components::PlayerId;
+    component components::PlayerPosition, quantize::serialize_position, quantize::deserialize_position
+        => add_prediction() => add_linear_interpolation() => add_delta_compression();
+    component components::PlayerVelocity => add_prediction();
+    component components::PlayerAppearance;
+    component components::Health;
+    component components::LifeState;
+    component components::Team;
+    component components::Npc;
+    component components::SpeedBoost => add_prediction();
+    component projectiles::Projectile => add_prediction();
+    component pickups::Pickup;
+    component pickups::Inventory;
+    component matches::MatchStatus;
+    component stats::PlayerStats;
+    component components::ObjectPosition => add_prediction() => add_linear_interpolation();
+
+    channel messages::LobbyChannel, reliable_channel(), NetworkDirection::Bidirectional;
+    channel messages::EventChannel, reliable_channel(), NetworkDirection::ServerToClient;
+    message messages::RoomCommand, NetworkDirection::ClientToServer;
+    message messages::RoomList, NetworkDirection::ServerToClient;
+    nested messages::RoomInfo;
+    message messages::SelectAppearance, NetworkDirection::ClientToServer;
+    message messages::SelectTeam, NetworkDirection::ClientToServer;
+    message messages::HitEvent, NetworkDirection::ServerToClient;
+    message messages::PlayerKilled, NetworkDirection::ServerToClient;
+    message messages::ProfileIssued, NetworkDirection::ServerToClient;
+    message stats::Standings, NetworkDirection::ServerToClient;
+    nested stats::StandingsEntry;
+}
 
 pub struct ProtocolPlugin;
 
 impl Plugin for ProtocolPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(input::native::InputPlugin::<components::Inputs>::default());
-        app.register_component::<components::PlayerId>();
-        app.register_component::<components::PlayerPosition>();
+        register_wire_types(app);
+
+        app.init_resource::<map::MapSettings>();
+        app.init_resource::<characters::CharacterRegistry>();
     }
 }
@@ -0,0 +1,62 @@
+use super::{components::*, map::MapSettings};
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Distance a projectile travels per tick
+pub const PROJECTILE_SPEED: f32 = 3.0;
+/// Ticks a projectile flies before it disappears
+pub const PROJECTILE_LIFETIME_TICKS: u16 = 96;
+/// Ticks between two shots of the same player
+pub const SHOOT_COOLDOWN_TICKS: u16 = 16;
+/// Distance in front of the shooter a projectile spawns at, so it doesn't hit the shooter
+pub const PROJECTILE_SPAWN_OFFSET: f32 = 8.0;
+
+/// A shot fired by the player `owner`. Its [`ObjectPosition`] is predicted, interpolated and
+/// filtered by the interest radius like the players.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct Projectile {
+    pub owner: PeerId,
+    /// Movement per tick
+    pub velocity: Vec2,
+}
+
+/// Ticks left before the projectile expires. Not replicated: the server and the predicting
+/// client count down on their own.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ProjectileLifetime(pub u16);
+
+/// Components of a new projectile, identical on the server and on the client that fired it
+pub fn projectile(owner: PeerId, shooter: Vec2, aim: Vec2, tick: Tick) -> impl Bundle {
+    let aim = aim.normalize_or(Vec2::X);
+    (
+        Projectile {
+            owner,
+            velocity: aim * PROJECTILE_SPEED,
+        },
+        ObjectPosition(shooter + aim * PROJECTILE_SPAWN_OFFSET),
+        ProjectileLifetime(PROJECTILE_LIFETIME_TICKS),
+        // lets the client match the projectile it predicted with the one the server spawned
+        PreSpawned::new(prespawn_hash(owner, tick)),
+    )
+}
+
+fn prespawn_hash(owner: PeerId, tick: Tick) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    owner.to_bits().hash(&mut hasher);
+    tick.0.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Moves a projectile by one tick. Returns `false` once it expired or hit a wall.
+pub fn shared_projectile_behaviour(
+    mut position: Mut<ObjectPosition>,
+    projectile: &Projectile,
+    mut lifetime: Mut<ProjectileLifetime>,
+    map: &MapSettings,
+) -> bool {
+    position.0 += projectile.velocity;
+    lifetime.0 = lifetime.0.saturating_sub(1);
+    lifetime.0 > 0 && !map.blocks(position.0)
+}
@@ -0,0 +1,430 @@
+//! Compact wire format of [`PlayerPosition`].
+//!
+//! Positions go out as fixed-point steps relative to the map bounds instead of two `f32`.
+//! Once a client acked a position, only the bit-packed difference to it is sent.
+//!
+//! The server and the predicting clients [`snap`] every position they simulate to the same
+//! grid, so what goes over the wire is exactly what was simulated. The grid is a constant
+//! over [`MAP_BOUNDS`], so both ends and the [`protocol_id`](super::version::protocol_id)
+//! always agree on it.
+
+use super::components::PlayerPosition;
+use super::map::MAP_BOUNDS;
+use bevy::prelude::*;
+use lightyear::prelude::Diffable;
+use lightyear::serde::{SerializationError, reader::Reader, writer::Writer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+/// Steps per world unit, positions are exact to 1/16 pixel
+pub const POSITION_STEPS: f32 = 16.0;
+/// Space around the map that still quantizes exactly, for NPCs turning back at the edge
+const MARGIN: f32 = 64.0;
+
+/// Bits needed to tell `count` values apart
+const fn bits_for(count: u32) -> u32 {
+    u32::BITS - (count - 1).leading_zeros()
+}
+
+/// Bits of the width field in front of a [`PositionDelta`]
+const WIDTH_BITS: u32 = 5;
+
+/// The fixed-point grid positions are quantized to, covering the map bounds and a margin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionGrid {
+    /// Anything outside is clamped to its border
+    area: Rect,
+    x_bits: u32,
+    y_bits: u32,
+}
+
+impl PositionGrid {
+    pub const fn new(bounds: Rect) -> Self {
+        let area = Rect {
+            min: Vec2::new(bounds.min.x - MARGIN, bounds.min.y - MARGIN),
+            max: Vec2::new(bounds.max.x + MARGIN, bounds.max.y + MARGIN),
+        };
+        Self {
+            area,
+            x_bits: bits_for(((area.max.x - area.min.x) * POSITION_STEPS) as u32 + 1),
+            y_bits: bits_for(((area.max.y - area.min.y) * POSITION_STEPS) as u32 + 1),
+        }
+    }
+
+    pub fn quantize(&self, position: Vec2) -> QuantizedPosition {
+        let steps = (position.clamp(self.area.min, self.area.max) - self.area.min) * POSITION_STEPS;
+        QuantizedPosition {
+            x: steps.x.round() as u16,
+            y: steps.y.round() as u16,
+        }
+    }
+
+    pub fn position(&self, quantized: QuantizedPosition) -> Vec2 {
+        self.area.min + Vec2::new(quantized.x as f32, quantized.y as f32) / POSITION_STEPS
+    }
+
+    /// The closest position the wire format can carry exactly
+    pub fn snap(&self, position: Vec2) -> Vec2 {
+        self.position(self.quantize(position))
+    }
+
+    /// Full value, sent when a client has nothing acked to diff against
+    pub fn pack(&self, quantized: QuantizedPosition) -> Vec<u8> {
+        let mut bits = BitWriter::default();
+        bits.write(quantized.x as u32, self.x_bits);
+        bits.write(quantized.y as u32, self.y_bits);
+        bits.finish()
+    }
+
+    pub fn unpack(&self, bytes: &[u8]) -> Option<QuantizedPosition> {
+        let mut bits = BitReader::new(bytes);
+        Some(QuantizedPosition {
+            x: bits.read(self.x_bits)? as u16,
+            y: bits.read(self.y_bits)? as u16,
+        })
+    }
+
+    /// Bytes of [`pack`](Self::pack)
+    pub fn packed_len(&self) -> usize {
+        (self.x_bits + self.y_bits).div_ceil(8) as usize
+    }
+
+    /// Describes the wire format for [`protocol_id`](super::version::protocol_id), which can't
+    /// see custom serialization through reflection
+    pub fn layout(&self) -> String {
+        format!(
+            "position {}+{} bits, delta {WIDTH_BITS} bit width, {POSITION_STEPS} steps from {:?}",
+            self.x_bits, self.y_bits, self.area
+        )
+    }
+}
+
+/// Grid every position is quantized to. Lightyear's serialization functions can't reach the
+/// world, and a grid per map would have to be negotiated, so every map has to fit into
+/// [`MAP_BOUNDS`].
+pub const GRID: PositionGrid = PositionGrid::new(MAP_BOUNDS);
+
+/// [`PositionGrid::snap`] on the [`GRID`]
+pub fn snap(position: Vec2) -> Vec2 {
+    GRID.snap(position)
+}
+
+/// Position in steps from the lower left corner of a [`PositionGrid`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuantizedPosition {
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Difference between two [`QuantizedPosition`]s. Both axes share the bit width of the larger
+/// one, which the packed form starts with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PositionDelta {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl PositionDelta {
+    pub fn between(from: QuantizedPosition, to: QuantizedPosition) -> Self {
+        Self {
+            x: to.x as i32 - from.x as i32,
+            y: to.y as i32 - from.y as i32,
+        }
+    }
+
+    pub fn apply(self, to: QuantizedPosition) -> QuantizedPosition {
+        QuantizedPosition {
+            x: (to.x as i32 + self.x) as u16,
+            y: (to.y as i32 + self.y) as u16,
+        }
+    }
+
+    pub fn pack(self) -> Vec<u8> {
+        let (x, y) = (zigzag(self.x), zigzag(self.y));
+        let width = bits_for(x.max(y) + 1);
+        let mut bits = BitWriter::default();
+        bits.write(width, WIDTH_BITS);
+        bits.write(x, width);
+        bits.write(y, width);
+        bits.finish()
+    }
+
+    pub fn unpack(bytes: &[u8]) -> Option<Self> {
+        let mut bits = BitReader::new(bytes);
+        let width = bits.read(WIDTH_BITS)?;
+        Some(Self {
+            x: unzigzag(bits.read(width)?),
+            y: unzigzag(bits.read(width)?),
+        })
+    }
+}
+
+// the delta goes through lightyear's serde path, as a byte string
+impl Serialize for PositionDelta {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.pack())
+    }
+}
+
+impl<'de> Deserialize<'de> for PositionDelta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DeltaVisitor;
+
+        impl<'de> de::Visitor<'de> for DeltaVisitor {
+            type Value = PositionDelta;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a packed position delta")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<PositionDelta, E> {
+                PositionDelta::unpack(bytes).ok_or_else(|| E::custom("truncated position delta"))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<PositionDelta, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                self.visit_bytes(&bytes)
+            }
+        }
+
+        deserializer.deserialize_bytes(DeltaVisitor)
+    }
+}
+
+impl Diffable for PlayerPosition {
+    type Delta = PositionDelta;
+
+    fn base_value() -> Self {
+        PlayerPosition(GRID.position(QuantizedPosition::default()))
+    }
+
+    fn diff(&self, new: &Self) -> PositionDelta {
+        PositionDelta::between(GRID.quantize(self.0), GRID.quantize(new.0))
+    }
+
+    fn apply_diff(&mut self, delta: &PositionDelta) {
+        self.0 = GRID.position(delta.apply(GRID.quantize(self.0)));
+    }
+}
+
+pub fn serialize_position(
+    position: &PlayerPosition,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    writer.write_all(&GRID.pack(GRID.quantize(position.0)))?;
+    Ok(())
+}
+
+pub fn deserialize_position(reader: &mut Reader) -> Result<PlayerPosition, SerializationError> {
+    let mut bytes = vec![0; GRID.packed_len()];
+    reader.read_exact(&mut bytes)?;
+    let quantized = GRID
+        .unpack(&bytes)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "truncated position"))?;
+    Ok(PlayerPosition(GRID.position(quantized)))
+}
+
+/// Maps small values of either sign to small unsigned values: 0, -1, 1, -2, ...
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.pending |= (value as u64 & ((1 << bits) - 1)) << self.pending_bits;
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.bytes.push(self.pending as u8);
+            self.pending >>= 8;
+            self.pending_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            self.bytes.push(self.pending as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pending: 0,
+            pending_bits: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> Option<u32> {
+        while self.pending_bits < bits {
+            let (byte, rest) = self.bytes.split_first()?;
+            self.pending |= (*byte as u64) << self.pending_bits;
+            self.pending_bits += 8;
+            self.bytes = rest;
+        }
+        let value = self.pending & ((1 << bits) - 1);
+        self.pending >>= bits;
+        self.pending_bits -= bits;
+        Some(value as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::map::MapSettings;
+
+    fn grid() -> PositionGrid {
+        GRID
+    }
+
+    #[test]
+    fn default_map_fits_the_grid() {
+        let bounds = MapSettings::default().bounds;
+        assert_eq!(grid().snap(bounds.min), bounds.min);
+        assert_eq!(grid().snap(bounds.max), bounds.max);
+    }
+
+    #[test]
+    fn packed_positions_round_trip() {
+        let grid = grid();
+        let bounds = MapSettings::default().bounds;
+        for position in [
+            bounds.min - MARGIN,
+            bounds.max + MARGIN,
+            Vec2::ZERO,
+            Vec2::new(-481.5, 12.25),
+            Vec2::new(479.9375, -269.0625),
+        ] {
+            let quantized = grid.quantize(position);
+            let packed = grid.pack(quantized);
+            assert_eq!(packed.len(), grid.packed_len());
+            assert_eq!(grid.unpack(&packed), Some(quantized));
+            assert_eq!(grid.position(quantized), position);
+        }
+    }
+
+    #[test]
+    fn positions_outside_the_grid_are_clamped() {
+        let grid = grid();
+        let far = Vec2::new(1.0e6, -1.0e6);
+        assert_eq!(grid.snap(far), far.clamp(grid.area.min, grid.area.max));
+    }
+
+    #[test]
+    fn snapping_is_exact_and_stable() {
+        let grid = grid();
+        let mut position = Vec2::new(-300.0, -200.0);
+        for _ in 0..1000 {
+            position = grid.snap(position + Vec2::new(0.4, -0.6));
+            assert_eq!(grid.snap(position), position);
+            assert_eq!(grid.position(grid.quantize(position)), position);
+        }
+        let off_grid = Vec2::new(10.01, -3.33);
+        assert!(grid.snap(off_grid).distance(off_grid) <= 0.5 / POSITION_STEPS * 2f32.sqrt());
+    }
+
+    #[test]
+    fn zigzag_round_trips() {
+        for value in [0, -1, 1, -2, 2, 1000, -1000, i32::MAX, i32::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!(
+            [0, -1, 1, -2, 2].map(zigzag),
+            [0, 1, 2, 3, 4],
+            "small values of either sign stay small"
+        );
+    }
+
+    #[test]
+    fn zero_delta_has_zero_width() {
+        let packed = PositionDelta::default().pack();
+        assert_eq!(packed.len(), 1);
+        assert_eq!(BitReader::new(&packed).read(WIDTH_BITS), Some(0));
+        assert_eq!(
+            PositionDelta::unpack(&packed),
+            Some(PositionDelta::default())
+        );
+    }
+
+    #[test]
+    fn largest_delta_fits_the_width_field() {
+        let grid = grid();
+        let corners = [
+            grid.quantize(grid.area.min),
+            grid.quantize(grid.area.max),
+            grid.quantize(Vec2::new(grid.area.min.x, grid.area.max.y)),
+        ];
+        for from in corners {
+            for to in corners {
+                let delta = PositionDelta::between(from, to);
+                assert_eq!(PositionDelta::unpack(&delta.pack()), Some(delta));
+                assert_eq!(delta.apply(from), to);
+            }
+        }
+        // 31 bits, the most `WIDTH_BITS` can announce
+        let widest = PositionDelta {
+            x: (1 << 30) - 1,
+            y: -(1 << 30),
+        };
+        let packed = widest.pack();
+        assert_eq!(BitReader::new(&packed).read(WIDTH_BITS), Some(31));
+        assert_eq!(PositionDelta::unpack(&packed), Some(widest));
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let grid = grid();
+        let packed = grid.pack(grid.quantize(Vec2::new(12.0, -7.5)));
+        assert_eq!(grid.unpack(&packed[..packed.len() - 1]), None);
+        assert_eq!(grid.unpack(&[]), None);
+
+        let delta = PositionDelta { x: -37, y: 1200 }.pack();
+        assert_eq!(PositionDelta::unpack(&delta[..delta.len() - 1]), None);
+        assert_eq!(PositionDelta::unpack(&[]), None);
+    }
+
+    #[test]
+    fn bit_reader_reads_across_bytes() {
+        let mut writer = BitWriter::default();
+        writer.write(0b101, 3);
+        writer.write(0x1ffff, 17);
+        writer.write(u32::MAX, 32);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 7);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read(3), Some(0b101));
+        assert_eq!(reader.read(17), Some(0x1ffff));
+        assert_eq!(reader.read(32), Some(u32::MAX));
+        assert_eq!(reader.read(8), None);
+    }
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use rand::{SeedableRng, rngs::StdRng};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// The game's only source of randomness.
+///
+/// Seeded by the OS, unless a `seed` is configured: tests and replays then get the same rolls
+/// on every run.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self(StdRng::from_os_rng())
+    }
+}
+
+impl GameRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+
+    /// Seeded by the `seed` of a JSON config file, by the OS if there is none
+    pub fn load(path: &Path) -> Self {
+        #[derive(Deserialize, Default)]
+        struct Config {
+            #[serde(default)]
+            seed: Option<u64>,
+        }
+
+        let config = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| {
+                serde_json::from_str::<Config>(&json)
+                    .inspect_err(|e| error!("Ignoring the invalid config {}: {e}", path.display()))
+                    .ok()
+            })
+            .unwrap_or_default();
+        match config.seed {
+            Some(seed) => {
+                info!("Seeding the random rolls with {seed}");
+                Self::seeded(seed)
+            }
+            None => Self::default(),
+        }
+    }
+}
@@ -0,0 +1,567 @@
+//! Compact binary replay format.
+//!
+//! A replay file starts with a [`ReplayHeader`] followed by a stream of [`ReplayRecord`]s.
+//! Every tick simulated by the server opens with [`ReplayRecord::Tick`], then lists the
+//! players that joined, left or were teleported since the previous tick, the inputs that
+//! were applied and the resulting positions. All numbers are little endian.
+
+use super::components::*;
+use bevy::{platform::collections::HashMap, prelude::*};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    time::Duration,
+};
+
+const MAGIC: &[u8; 4] = b"JIMR";
+const FORMAT_VERSION: u16 = 1;
+
+const TAG_TICK: u8 = 0;
+const TAG_CONNECT: u8 = 1;
+const TAG_DISCONNECT: u8 = 2;
+const TAG_INPUT: u8 = 3;
+const TAG_POSITION: u8 = 4;
+const TAG_TELEPORT: u8 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayHeader {
+    /// [`protocol_id`](super::version::protocol_id) of the server that recorded the match
+    pub protocol_version: u64,
+    /// Duration of one server tick
+    pub tick_duration: Duration,
+    /// Name of the map the match was played on
+    pub map: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayRecord {
+    /// Start of the records of one simulated tick
+    Tick(u32),
+    /// A player entity was spawned at `position`
+    Connect { player: u64, position: Vec2 },
+    /// A player entity was despawned
+    Disconnect { player: u64 },
+    /// Inputs the server applied to a player during the current tick, `boosted` while a
+    /// [`SpeedBoost`] was active
+    Input {
+        player: u64,
+        inputs: Inputs,
+        boosted: bool,
+    },
+    /// Position of a player at the end of the current tick
+    Position { player: u64, position: Vec2 },
+    /// A player was moved by the server before the inputs of the current tick, e.g. on respawn
+    Teleport { player: u64, position: Vec2 },
+}
+
+impl ReplayHeader {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        w.write_all(&self.protocol_version.to_le_bytes())?;
+        w.write_all(&(self.tick_duration.as_micros() as u32).to_le_bytes())?;
+        let map = self.map.as_bytes();
+        w.write_all(&(map.len() as u16).to_le_bytes())?;
+        w.write_all(map)
+    }
+
+    pub fn read_from(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a replay file"));
+        }
+        let format_version = read_u16(r)?;
+        if format_version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported replay format version {format_version}"),
+            ));
+        }
+        let protocol_version = read_u64(r)?;
+        let tick_duration = Duration::from_micros(read_u32(r)? as u64);
+        let mut map = vec![0; read_u16(r)? as usize];
+        r.read_exact(&mut map)?;
+        let map = String::from_utf8(map)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "map name is not utf-8"))?;
+
+        Ok(Self {
+            protocol_version,
+            tick_duration,
+            map,
+        })
+    }
+}
+
+impl ReplayRecord {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        match self {
+            ReplayRecord::Tick(tick) => {
+                w.write_all(&[TAG_TICK])?;
+                w.write_all(&tick.to_le_bytes())
+            }
+            ReplayRecord::Connect { player, position } => {
+                w.write_all(&[TAG_CONNECT])?;
+                w.write_all(&player.to_le_bytes())?;
+                write_vec2(w, *position)
+            }
+            ReplayRecord::Disconnect { player } => {
+                w.write_all(&[TAG_DISCONNECT])?;
+                w.write_all(&player.to_le_bytes())
+            }
+            ReplayRecord::Input {
+                player,
+                inputs,
+                boosted,
+            } => {
+                w.write_all(&[TAG_INPUT])?;
+                w.write_all(&player.to_le_bytes())?;
+                write_inputs(w, inputs)?;
+                w.write_all(&[*boosted as u8])
+            }
+            ReplayRecord::Position { player, position } => {
+                w.write_all(&[TAG_POSITION])?;
+                w.write_all(&player.to_le_bytes())?;
+                write_vec2(w, *position)
+            }
+            ReplayRecord::Teleport { player, position } => {
+                w.write_all(&[TAG_TELEPORT])?;
+                w.write_all(&player.to_le_bytes())?;
+                write_vec2(w, *position)
+            }
+        }
+    }
+
+    /// Reads the next record, `None` at the end of the file
+    pub fn read_from(r: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut tag = [0; 1];
+        match r.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let record = match tag[0] {
+            TAG_TICK => ReplayRecord::Tick(read_u32(r)?),
+            TAG_CONNECT => ReplayRecord::Connect {
+                player: read_u64(r)?,
+                position: read_vec2(r)?,
+            },
+            TAG_DISCONNECT => ReplayRecord::Disconnect {
+                player: read_u64(r)?,
+            },
+            TAG_INPUT => ReplayRecord::Input {
+                player: read_u64(r)?,
+                inputs: read_inputs(r)?,
+                boosted: read_u8(r)? != 0,
+            },
+            TAG_POSITION => ReplayRecord::Position {
+                player: read_u64(r)?,
+                position: read_vec2(r)?,
+            },
+            TAG_TELEPORT => ReplayRecord::Teleport {
+                player: read_u64(r)?,
+                position: read_vec2(r)?,
+            },
+            tag => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown replay record {tag}"),
+                ));
+            }
+        };
+        Ok(Some(record))
+    }
+}
+
+/// A fully loaded replay file
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub records: Vec<ReplayRecord>,
+}
+
+/// Positions of every player at the end of one tick
+#[derive(Clone, Debug, Default)]
+pub struct ReplayFrame {
+    pub tick: u32,
+    pub positions: Vec<(u64, Vec2)>,
+}
+
+/// The first position where the re-simulation disagrees with the recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct Desync {
+    pub tick: u32,
+    pub player: u64,
+    pub recorded: Vec2,
+    pub simulated: Vec2,
+}
+
+impl Replay {
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let header = ReplayHeader::read_from(r)?;
+        let mut records = Vec::new();
+        while let Some(record) = ReplayRecord::read_from(r)? {
+            records.push(record);
+        }
+        Ok(Self { header, records })
+    }
+
+    /// Recorded positions grouped per tick, for playback
+    pub fn frames(&self) -> Vec<ReplayFrame> {
+        let mut frames: Vec<ReplayFrame> = Vec::new();
+        for record in &self.records {
+            match record {
+                ReplayRecord::Tick(tick) => frames.push(ReplayFrame {
+                    tick: *tick,
+                    ..default()
+                }),
+                ReplayRecord::Position { player, position } => {
+                    if let Some(frame) = frames.last_mut() {
+                        frame.positions.push((*player, *position));
+                    }
+                }
+                _ => {}
+            }
+        }
+        frames
+    }
+
+    /// Re-simulates the recorded inputs with [`shared_movement_behaviour`] and checks that
+    /// every recorded position is reproduced exactly.
+    ///
+    /// Returns the number of simulated ticks.
+    pub fn verify(&self) -> Result<u32, Desync> {
+        let mut world = World::new();
+        let mut movers = world.query::<(&mut PlayerPosition, &mut PlayerVelocity)>();
+        let mut players = HashMap::<u64, Entity>::new();
+        let mut tick = 0;
+        let mut ticks = 0;
+
+        for record in &self.records {
+            match record {
+                ReplayRecord::Tick(t) => {
+                    tick = *t;
+                    ticks += 1;
+                }
+                ReplayRecord::Connect { player, position } => {
+                    let entity = world
+                        .spawn((PlayerPosition(*position), PlayerVelocity::default()))
+                        .id();
+                    players.insert(*player, entity);
+                }
+                ReplayRecord::Disconnect { player } => {
+                    if let Some(entity) = players.remove(player) {
+                        world.despawn(entity);
+                    }
+                }
+                ReplayRecord::Teleport { player, position } => {
+                    if let Some(mut simulated) = players
+                        .get(player)
+                        .and_then(|entity| world.get_mut::<PlayerPosition>(*entity))
+                    {
+                        simulated.0 = *position;
+                    }
+                }
+                ReplayRecord::Input {
+                    player,
+                    inputs,
+                    boosted,
+                } => {
+                    let Some(&entity) = players.get(player) else {
+                        continue;
+                    };
+                    if let Ok((position, velocity)) = movers.get_mut(&mut world, entity) {
+                        shared_movement_behaviour(position, velocity, inputs, *boosted);
+                    }
+                }
+                ReplayRecord::Position { player, position } => {
+                    let simulated = players
+                        .get(player)
+                        .and_then(|entity| world.get::<PlayerPosition>(*entity))
+                        .map(|simulated| simulated.0);
+                    if simulated != Some(*position) {
+                        return Err(Desync {
+                            tick,
+                            player: *player,
+                            recorded: *position,
+                            simulated: simulated.unwrap_or(Vec2::NAN),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(ticks)
+    }
+}
+
+const INPUT_DIRECTION: u8 = 0;
+const INPUT_ATTACK: u8 = 1;
+const INPUT_SHOOT: u8 = 2;
+
+fn write_inputs(w: &mut impl Write, inputs: &Inputs) -> io::Result<()> {
+    match inputs {
+        Inputs::Direction(direction) => w.write_all(&[INPUT_DIRECTION, direction_bits(direction)]),
+        Inputs::Attack {
+            direction,
+            view_tick,
+        } => {
+            w.write_all(&[INPUT_ATTACK, direction_bits(direction)])?;
+            w.write_all(&view_tick.to_le_bytes())
+        }
+        Inputs::Shoot { direction, aim } => {
+            w.write_all(&[INPUT_SHOOT, direction_bits(direction)])?;
+            write_vec2(w, *aim)
+        }
+    }
+}
+
+fn read_inputs(r: &mut impl Read) -> io::Result<Inputs> {
+    let mut bytes = [0; 2];
+    r.read_exact(&mut bytes)?;
+    let [kind, bits] = bytes;
+    let direction = direction_from_bits(bits);
+    match kind {
+        INPUT_DIRECTION => Ok(Inputs::Direction(direction)),
+        INPUT_ATTACK => Ok(Inputs::Attack {
+            direction,
+            view_tick: read_u16(r)?,
+        }),
+        INPUT_SHOOT => Ok(Inputs::Shoot {
+            direction,
+            aim: read_vec2(r)?,
+        }),
+        kind => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unknown input kind {kind}"),
+        )),
+    }
+}
+
+fn direction_bits(direction: &Direction) -> u8 {
+    (direction.up as u8)
+        | ((direction.down as u8) << 1)
+        | ((direction.left as u8) << 2)
+        | ((direction.right as u8) << 3)
+}
+
+fn direction_from_bits(bits: u8) -> Direction {
+    Direction {
+        up: bits & 1 != 0,
+        down: bits & (1 << 1) != 0,
+        left: bits & (1 << 2) != 0,
+        right: bits & (1 << 3) != 0,
+    }
+}
+
+fn write_vec2(w: &mut impl Write, v: Vec2) -> io::Result<()> {
+    w.write_all(&v.x.to_le_bytes())?;
+    w.write_all(&v.y.to_le_bytes())
+}
+
+fn read_vec2(r: &mut impl Read) -> io::Result<Vec2> {
+    Ok(Vec2::new(read_f32(r)?, read_f32(r)?))
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    r.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(r)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::quantize;
+
+    fn walk(right: bool, up: bool) -> Inputs {
+        Inputs::Direction(Direction {
+            right,
+            up,
+            ..default()
+        })
+    }
+
+    /// Player 7 joins, walks right, is teleported and walks up boosted
+    fn recording() -> Replay {
+        let start = Vec2::new(10.0, -4.0);
+        let walked = quantize::snap(start + Vec2::new(MOVE_SPEED, 0.0));
+        let respawn = Vec2::new(-100.0, 50.0);
+        let boosted = quantize::snap(respawn + Vec2::new(0.0, MOVE_SPEED * SPEED_BOOST_MULTIPLIER));
+        Replay {
+            header: ReplayHeader {
+                protocol_version: 0x1234_5678_9abc_def0,
+                tick_duration: Duration::from_micros(15_625),
+                map: "arena".to_string(),
+            },
+            records: vec![
+                ReplayRecord::Tick(1),
+                ReplayRecord::Connect {
+                    player: 7,
+                    position: start,
+                },
+                ReplayRecord::Input {
+                    player: 7,
+                    inputs: walk(true, false),
+                    boosted: false,
+                },
+                ReplayRecord::Position {
+                    player: 7,
+                    position: walked,
+                },
+                ReplayRecord::Tick(2),
+                ReplayRecord::Teleport {
+                    player: 7,
+                    position: respawn,
+                },
+                ReplayRecord::Input {
+                    player: 7,
+                    inputs: walk(false, true),
+                    boosted: true,
+                },
+                ReplayRecord::Position {
+                    player: 7,
+                    position: boosted,
+                },
+                ReplayRecord::Tick(3),
+                ReplayRecord::Input {
+                    player: 7,
+                    inputs: Inputs::Shoot {
+                        direction: Direction::default(),
+                        aim: Vec2::new(0.6, -0.8),
+                    },
+                    boosted: false,
+                },
+                ReplayRecord::Input {
+                    player: 7,
+                    inputs: Inputs::Attack {
+                        direction: Direction::default(),
+                        view_tick: 65_000,
+                    },
+                    boosted: false,
+                },
+                ReplayRecord::Position {
+                    player: 7,
+                    position: boosted,
+                },
+                ReplayRecord::Disconnect { player: 7 },
+            ],
+        }
+    }
+
+    fn encode(replay: &Replay) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        replay.header.write_to(&mut bytes).unwrap();
+        for record in &replay.records {
+            record.write_to(&mut bytes).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = recording();
+        let decoded = Replay::read(&mut encode(&replay).as_slice()).unwrap();
+        assert_eq!(decoded.header, replay.header);
+        assert_eq!(decoded.records, replay.records);
+    }
+
+    #[test]
+    fn every_direction_survives_the_bits() {
+        for bits in 0..16 {
+            assert_eq!(direction_bits(&direction_from_bits(bits)), bits);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = encode(&recording());
+        bytes[0] = b'X';
+        let error = Replay::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut bytes = encode(&recording());
+        bytes[4] = bytes[4].wrapping_add(1);
+        let error = Replay::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        let bytes = encode(&recording());
+        let error = Replay::read(&mut &bytes[..bytes.len() - 3]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn unknown_input_kind_is_an_error() {
+        let mut bytes = Vec::new();
+        ReplayRecord::Input {
+            player: 1,
+            inputs: walk(true, false),
+            boosted: false,
+        }
+        .write_to(&mut bytes)
+        .unwrap();
+        // tag and player, then the input kind
+        bytes[9] = 9;
+        let error = ReplayRecord::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn frames_group_positions_per_tick() {
+        let frames = recording().frames();
+        let ticks: Vec<_> = frames.iter().map(|frame| frame.tick).collect();
+        assert_eq!(ticks, [1, 2, 3]);
+        assert!(frames.iter().all(|frame| frame.positions.len() == 1));
+    }
+
+    #[test]
+    fn verify_reproduces_the_recording() {
+        let replay = Replay::read(&mut encode(&recording()).as_slice()).unwrap();
+        assert_eq!(replay.verify(), Ok(3));
+    }
+
+    #[test]
+    fn verify_finds_the_first_desync() {
+        let mut replay = recording();
+        let ReplayRecord::Position { position, .. } = &mut replay.records[7] else {
+            panic!("not a position");
+        };
+        let recorded = *position + Vec2::new(1.0, 0.0);
+        let simulated = *position;
+        *position = recorded;
+
+        assert_eq!(
+            replay.verify(),
+            Err(Desync {
+                tick: 2,
+                player: 7,
+                recorded,
+                simulated,
+            })
+        );
+    }
+}
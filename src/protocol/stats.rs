@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use lightyear::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Points for a kill
+pub const KILL_SCORE: u32 = 10;
+/// Points for a collected pickup
+pub const PICKUP_SCORE: u32 = 1;
+
+/// Counters of the current round, kept by the server
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Reflect,
+)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub pickups: u32,
+    pub score: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct StandingsEntry {
+    pub player: PeerId,
+    /// Profile name of the player
+    pub name: String,
+    pub stats: PlayerStats,
+}
+
+/// Sorted standings of the room, sent to its clients whenever they change. Unlike the
+/// replicated [`PlayerStats`] they include the players outside the interest radius.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Reflect)]
+pub struct Standings {
+    pub entries: Vec<StandingsEntry>,
+}
+
+/// Order of the standings: highest score first, then most kills, then fewest deaths. The
+/// peer id breaks the remaining ties so that the server and every client agree.
+pub fn compare_standings(a: (PeerId, &PlayerStats), b: (PeerId, &PlayerStats)) -> Ordering {
+    let (a_id, a) = a;
+    let (b_id, b) = b;
+    b.score
+        .cmp(&a.score)
+        .then(b.kills.cmp(&a.kills))
+        .then(a.deaths.cmp(&b.deaths))
+        .then(a_id.to_bits().cmp(&b_id.to_bits()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(score: u32, kills: u32, deaths: u32) -> PlayerStats {
+        PlayerStats {
+            kills,
+            deaths,
+            pickups: 0,
+            score,
+        }
+    }
+
+    #[test]
+    fn ties_are_broken_by_kills_deaths_then_peer() {
+        let mut standings = vec![
+            (PeerId::Netcode(4), stats(20, 2, 1)),
+            (PeerId::Netcode(3), stats(20, 2, 1)),
+            (PeerId::Netcode(1), stats(20, 1, 0)),
+            (PeerId::Netcode(2), stats(20, 2, 3)),
+            (PeerId::Netcode(5), stats(30, 0, 9)),
+        ];
+        standings.sort_by(|a, b| compare_standings((a.0, &a.1), (b.0, &b.1)));
+        let order: Vec<_> = standings.iter().map(|(peer, _)| *peer).collect();
+        assert_eq!(
+            order,
+            [
+                PeerId::Netcode(5),
+                PeerId::Netcode(3),
+                PeerId::Netcode(4),
+                PeerId::Netcode(2),
+                PeerId::Netcode(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn equal_standings_only_tie_with_themselves() {
+        let a = stats(10, 1, 1);
+        assert_eq!(
+            compare_standings((PeerId::Netcode(1), &a), (PeerId::Netcode(1), &a)),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare_standings((PeerId::Netcode(1), &a), (PeerId::Netcode(2), &a)),
+            compare_standings((PeerId::Netcode(2), &a), (PeerId::Netcode(1), &a)).reverse()
+        );
+    }
+}
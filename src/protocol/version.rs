@@ -0,0 +1,273 @@
+use super::{messages, plugin, quantize};
+use bevy::reflect::{Reflect, TypeInfo, VariantInfo};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::OnceLock};
+
+/// Semantic version of this build, sent to the server in the [`Hello`](messages::Hello)
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Id netcode checks when a client connects. It never changes, so that clients of every
+/// version get far enough to be told to update instead of timing out.
+pub const NETCODE_PROTOCOL_ID: u64 = 0x6a69_6d32_35;
+
+/// Hash of the field layouts and settings of every [`wire_types`](plugin::wire_types), in
+/// registration order. Builds that disagree on it can't decode each other's packets.
+pub fn protocol_id() -> u64 {
+    static PROTOCOL_ID: OnceLock<u64> = OnceLock::new();
+    *PROTOCOL_ID.get_or_init(|| {
+        let mut hasher = LayoutHasher::default();
+        for wire in plugin::wire_types() {
+            hasher.write(wire.kind);
+            match wire.info {
+                Some(info) => hasher.write_type(info),
+                None => hasher.write(wire.name),
+            }
+            hasher.write(&wire.settings);
+        }
+        hasher.write(&quantize::GRID.layout());
+        hasher.0
+    })
+}
+
+/// FNV-1a, unlike the std hashers it is guaranteed to stay the same across Rust releases
+struct LayoutHasher(u64);
+
+impl Default for LayoutHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl LayoutHasher {
+    fn write(&mut self, text: &str) {
+        // the separator keeps "ab", "c" apart from "a", "bc"
+        for byte in text.bytes().chain([0]) {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Field types are walked into, so that a change in a nested struct changes the hash
+    fn write_field(&mut self, name: &str, type_path: &str, info: Option<&'static TypeInfo>) {
+        self.write(name);
+        match info {
+            Some(info) => self.write_type(info),
+            None => self.write(type_path),
+        }
+    }
+
+    fn write_type(&mut self, info: &'static TypeInfo) {
+        self.write(info.type_path());
+        match info {
+            TypeInfo::Struct(info) => {
+                for field in info.iter() {
+                    self.write_field(field.name(), field.type_path(), field.type_info());
+                }
+            }
+            TypeInfo::TupleStruct(info) => {
+                for field in info.iter() {
+                    self.write_field("", field.type_path(), field.type_info());
+                }
+            }
+            TypeInfo::Enum(info) => {
+                for variant in info.iter() {
+                    self.write(variant.name());
+                    match variant {
+                        VariantInfo::Struct(variant) => {
+                            for field in variant.iter() {
+                                self.write_field(
+                                    field.name(),
+                                    field.type_path(),
+                                    field.type_info(),
+                                );
+                            }
+                        }
+                        VariantInfo::Tuple(variant) => {
+                            for field in variant.iter() {
+                                self.write_field("", field.type_path(), field.type_info());
+                            }
+                        }
+                        VariantInfo::Unit(_) => {}
+                    }
+                }
+            }
+            // primitives, collections and opaque types are identified by their path alone
+            _ => {}
+        }
+    }
+}
+
+/// `major.minor` of a semantic version. Builds that share it speak the same game rules.
+fn release(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Whether a client of `client_version` may join a server of `server_version`. Patch
+/// releases stay compatible with each other.
+pub fn is_compatible(client_version: &str, server_version: &str) -> bool {
+    release(client_version).is_some_and(|client| Some(client) == release(server_version))
+}
+
+/// Why the server turned a client away
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Reflect)]
+pub enum RejectReason {
+    /// The client runs another release than the server
+    UpdateRequired { server_version: String },
+    /// Same release, but the client's packets are laid out differently
+    ProtocolMismatch,
+    /// The client never introduced itself, it predates the handshake
+    NoHello,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::UpdateRequired { server_version } => write!(
+                f,
+                "Update required: the server runs version {server_version}, you have {GAME_VERSION}"
+            ),
+            RejectReason::ProtocolMismatch => write!(
+                f,
+                "Update required: this build of version {GAME_VERSION} does not match the server's"
+            ),
+            RejectReason::NoHello => write!(f, "The client did not introduce itself in time"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::reflect::Typed;
+
+    // the same path with different layouts, like one type in two builds
+    mod before {
+        use bevy::reflect::Reflect;
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Inner"]
+        pub struct Inner {
+            pub x: f32,
+        }
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Outer"]
+        pub struct Outer {
+            pub inner: Inner,
+        }
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Kind"]
+        pub enum Kind {
+            A,
+            B(u8),
+        }
+    }
+
+    mod after {
+        use bevy::reflect::Reflect;
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Inner"]
+        pub struct Inner {
+            pub y: f32,
+        }
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Outer"]
+        pub struct Outer {
+            pub inner: Inner,
+        }
+
+        #[derive(Reflect)]
+        #[type_path = "wire"]
+        #[type_name = "Kind"]
+        pub enum Kind {
+            A,
+            B(u16),
+        }
+    }
+
+    fn hash(info: &'static TypeInfo) -> u64 {
+        let mut hasher = LayoutHasher::default();
+        hasher.write_type(info);
+        hasher.0
+    }
+
+    #[test]
+    fn hasher_is_fnv_1a() {
+        let mut hasher = LayoutHasher::default();
+        hasher.write("");
+        // FNV-1a of a single zero byte
+        assert_eq!(hasher.0, 0xaf63_bd4c_8601_b7df);
+    }
+
+    #[test]
+    fn same_layout_same_hash() {
+        assert_eq!(
+            hash(before::Outer::type_info()),
+            hash(before::Outer::type_info())
+        );
+    }
+
+    #[test]
+    fn layout_changes_change_the_hash() {
+        assert_ne!(
+            hash(before::Inner::type_info()),
+            hash(after::Inner::type_info())
+        );
+        // through the field of another type
+        assert_ne!(
+            hash(before::Outer::type_info()),
+            hash(after::Outer::type_info())
+        );
+        assert_ne!(
+            hash(before::Kind::type_info()),
+            hash(after::Kind::type_info())
+        );
+    }
+
+    #[test]
+    fn protocol_id_is_stable() {
+        assert_eq!(protocol_id(), protocol_id());
+        assert_ne!(protocol_id(), NETCODE_PROTOCOL_ID);
+    }
+
+    #[test]
+    fn wire_types_start_with_the_handshake() {
+        let wire = plugin::wire_types();
+        assert_eq!(wire[0].name, "messages::HandshakeChannel");
+        assert_eq!(wire[1].name, "messages::Hello");
+        assert_eq!(wire[2].name, "messages::Rejected");
+        for (index, entry) in wire.iter().enumerate() {
+            assert!(
+                wire[..index].iter().all(|other| other.name != entry.name),
+                "{} is registered twice",
+                entry.name
+            );
+        }
+    }
+
+    #[test]
+    fn patch_releases_are_compatible() {
+        assert!(is_compatible("1.4.0", "1.4.0"));
+        assert!(is_compatible("1.4.2", "1.4.0"));
+        assert!(is_compatible("1.4.0-beta", "1.4.7"));
+        assert!(is_compatible(GAME_VERSION, GAME_VERSION));
+    }
+
+    #[test]
+    fn other_releases_are_not() {
+        assert!(!is_compatible("1.5.0", "1.4.0"));
+        assert!(!is_compatible("2.4.0", "1.4.0"));
+        assert!(!is_compatible("1", "1.4.0"));
+        assert!(!is_compatible("", ""));
+        assert!(!is_compatible("x.y.z", "x.y.z"));
+    }
+}
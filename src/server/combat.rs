@@ -0,0 +1,233 @@
+use super::health::Damage;
+use super::matches::Frozen;
+use super::validation::Throttled;
+use crate::protocol::{components::*, messages::*};
+use bevy::prelude::*;
+use lightyear::prelude::input::native::ActionState;
+use lightyear::prelude::server::{ClientOf, Server};
+use lightyear::prelude::*;
+use std::collections::VecDeque;
+
+#[derive(Resource, Clone, Debug)]
+pub struct CombatSettings {
+    /// Reach of a melee swing
+    pub melee_range: f32,
+    pub melee_damage: u32,
+    pub projectile_damage: u32,
+    /// Distance between a projectile and a player at which the projectile hits
+    pub projectile_hit_radius: f32,
+    /// Ticks between two swings of the same player
+    pub melee_cooldown_ticks: u16,
+    /// Ticks of positions kept per player
+    pub history_ticks: usize,
+    /// Most ticks a hit is rewound, older view ticks are clamped to it
+    pub max_rewind_ticks: u16,
+}
+
+impl Default for CombatSettings {
+    fn default() -> Self {
+        Self {
+            melee_range: 20.0,
+            melee_damage: 25,
+            projectile_damage: 20,
+            projectile_hit_radius: 8.0,
+            melee_cooldown_ticks: 24,
+            history_ticks: 64,
+            max_rewind_ticks: 32,
+        }
+    }
+}
+
+/// Bounded history of the confirmed positions of a player, oldest first
+#[derive(Component, Default, Debug)]
+pub struct PositionHistory {
+    samples: VecDeque<(Tick, Vec2)>,
+}
+
+impl PositionHistory {
+    fn record(&mut self, tick: Tick, position: Vec2, capacity: usize) {
+        if self.samples.len() >= capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((tick, position));
+    }
+
+    /// Position at `tick`, or the oldest one known if the history doesn't reach back that far
+    pub fn at(&self, tick: Tick) -> Option<Vec2> {
+        self.samples
+            .iter()
+            .rev()
+            .find(|(sample, _)| tick.0.wrapping_sub(sample.0) as i16 >= 0)
+            .or(self.samples.front())
+            .map(|(_, position)| *position)
+    }
+
+    /// Forgets every sample, for teleports
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// Ticks until the player may swing again
+#[derive(Component, Default, Debug)]
+pub struct MeleeCooldown(u16);
+
+/// A melee swing connected
+#[derive(Message, Clone, Debug)]
+pub struct Hit {
+    pub attacker: Entity,
+    pub target: Entity,
+    /// Where the target was at the attacker's view tick
+    pub position: Vec2,
+}
+
+pub fn track_new_player(trigger: On<Add, PlayerId>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity)
+        .insert((PositionHistory::default(), MeleeCooldown::default()));
+}
+
+/// Must run after the movement so that the history holds the confirmed position of the tick
+pub fn record_history(
+    mut players: Query<(&PlayerPosition, &mut PositionHistory)>,
+    server: Single<&LocalTimeline, With<Server>>,
+    settings: Res<CombatSettings>,
+) {
+    let tick = server.tick();
+    for (position, mut history) in &mut players {
+        history.record(tick, position.0, settings.history_ticks);
+    }
+}
+
+/// Resolves melee swings against the other players of the room, rewound to where the
+/// attacker saw them when swinging
+pub fn resolve_melee(
+    mut attackers: Query<
+        (
+            Entity,
+            &ActionState<Inputs>,
+            &PlayerPosition,
+            &RoomId,
+            &LifeState,
+            &mut MeleeCooldown,
+        ),
+        (Without<Throttled>, Without<Frozen>),
+    >,
+    targets: Query<(Entity, &PositionHistory, &RoomId, &LifeState), With<PlayerId>>,
+    server: Single<&LocalTimeline, With<Server>>,
+    settings: Res<CombatSettings>,
+    mut hits: MessageWriter<Hit>,
+    mut damage: MessageWriter<Damage>,
+) {
+    let tick = server.tick();
+    for (attacker, inputs, position, room, life, mut cooldown) in &mut attackers {
+        if cooldown.0 > 0 {
+            cooldown.0 -= 1;
+            continue;
+        }
+        if !life.is_alive() {
+            continue;
+        }
+        let Inputs::Attack { view_tick, .. } = inputs.0 else {
+            continue;
+        };
+        cooldown.0 = settings.melee_cooldown_ticks;
+
+        let rewind =
+            (tick.0.wrapping_sub(view_tick) as i16).clamp(0, settings.max_rewind_ticks as i16);
+        let seen_tick = Tick(tick.0.wrapping_sub(rewind as u16));
+        for (target, history, target_room, target_life) in &targets {
+            if target == attacker || target_room != room || !target_life.is_alive() {
+                continue;
+            }
+            let Some(seen) = history.at(seen_tick) else {
+                continue;
+            };
+            if seen.distance(position.0) <= settings.melee_range {
+                hits.write(Hit {
+                    attacker,
+                    target,
+                    position: seen,
+                });
+                damage.write(Damage {
+                    target,
+                    amount: settings.melee_damage,
+                    source: Some(attacker),
+                });
+            }
+        }
+    }
+}
+
+/// Tells the clients of the room about every hit so they can show an effect
+pub fn broadcast_hits(
+    mut hits: MessageReader<Hit>,
+    players: Query<(&PlayerId, &RoomId)>,
+    mut links: Query<(&RoomId, &mut MessageSender<HitEvent>), With<ClientOf>>,
+) {
+    for hit in hits.read() {
+        let (Ok((attacker, room)), Ok((target, _))) =
+            (players.get(hit.attacker), players.get(hit.target))
+        else {
+            continue;
+        };
+        let event = HitEvent {
+            attacker: attacker.0,
+            target: target.0,
+            position: hit.position,
+        };
+        for (link_room, mut sender) in &mut links {
+            if link_room == room {
+                sender.send::<EventChannel>(event.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(ticks: impl IntoIterator<Item = u16>, capacity: usize) -> PositionHistory {
+        let mut history = PositionHistory::default();
+        for tick in ticks {
+            history.record(Tick(tick), Vec2::new(tick as f32, 0.0), capacity);
+        }
+        history
+    }
+
+    #[test]
+    fn finds_the_latest_sample_up_to_the_tick() {
+        let history = history([10, 12, 14], 8);
+        assert_eq!(history.at(Tick(12)), Some(Vec2::new(12.0, 0.0)));
+        assert_eq!(history.at(Tick(13)), Some(Vec2::new(12.0, 0.0)));
+        assert_eq!(history.at(Tick(20)), Some(Vec2::new(14.0, 0.0)));
+        assert_eq!(PositionHistory::default().at(Tick(0)), None);
+    }
+
+    #[test]
+    fn looks_back_across_the_tick_wrap() {
+        let history = history([u16::MAX - 1, u16::MAX, 0, 1], 8);
+        assert_eq!(history.at(Tick(0)), Some(Vec2::new(0.0, 0.0)));
+        assert_eq!(
+            history.at(Tick(u16::MAX)),
+            Some(Vec2::new(u16::MAX as f32, 0.0))
+        );
+        assert_eq!(history.at(Tick(5)), Some(Vec2::new(1.0, 0.0)));
+    }
+
+    #[test]
+    fn falls_back_to_the_oldest_sample() {
+        let history = history([100, 101, 102], 8);
+        assert_eq!(history.at(Tick(50)), Some(Vec2::new(100.0, 0.0)));
+    }
+
+    #[test]
+    fn record_forgets_the_oldest_samples_beyond_capacity() {
+        let mut history = history(0..10, 4);
+        assert_eq!(history.samples.len(), 4);
+        assert_eq!(history.at(Tick(0)), Some(Vec2::new(6.0, 0.0)));
+        history.clear();
+        assert_eq!(history.at(Tick(9)), None);
+    }
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use std::sync::{
+    Mutex,
+    mpsc::{self, Receiver},
+};
+
+/// Admin command typed into the server's standard input
+#[derive(Message, Clone, Debug, PartialEq)]
+pub enum ConsoleCommand {
+    ListRooms,
+    CreateRoom {
+        name: String,
+    },
+    CloseRoom {
+        room: u32,
+    },
+    /// Write the world snapshot now instead of waiting for the shutdown
+    Snapshot,
+}
+
+const HELP: &str = "commands:
+  rooms               list the rooms and their players
+  room create <name>  open a new room
+  room close <id>     close a room and move its players to the lobby
+  snapshot            save the world to the snapshot file";
+
+impl ConsoleCommand {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        match (words.next()?, words.next()) {
+            ("rooms", None) => Some(ConsoleCommand::ListRooms),
+            ("snapshot", None) => Some(ConsoleCommand::Snapshot),
+            ("room", Some("create")) => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                (!name.is_empty()).then_some(ConsoleCommand::CreateRoom { name })
+            }
+            ("room", Some("close")) => Some(ConsoleCommand::CloseRoom {
+                room: words.next()?.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Lines read from stdin on a background thread, so the app never blocks on the terminal
+#[derive(Resource)]
+pub struct ConsoleInput(Mutex<Receiver<String>>);
+
+pub fn start_console(mut commands: Commands) {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    commands.insert_resource(ConsoleInput(Mutex::new(receiver)));
+}
+
+pub fn read_console(input: Res<ConsoleInput>, mut console: MessageWriter<ConsoleCommand>) {
+    let receiver = input.0.lock().unwrap();
+    for line in receiver.try_iter() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match ConsoleCommand::parse(&line) {
+            Some(command) => {
+                console.write(command);
+            }
+            None => println!("unknown command '{}'\n{HELP}", line.trim()),
+        }
+    }
+}
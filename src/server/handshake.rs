@@ -0,0 +1,158 @@
+use super::profiles::ProfileClaim;
+use crate::protocol::{messages::*, version::*};
+use bevy::prelude::*;
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use std::time::Duration;
+
+#[derive(Resource, Clone, Debug)]
+pub struct HandshakeSettings {
+    /// How long a new client has to send its [`Hello`]
+    pub timeout: Duration,
+    /// How long a rejected client stays connected, so that it receives the [`Rejected`]
+    pub linger: Duration,
+}
+
+impl Default for HandshakeSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            linger: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Link that did not send its [`Hello`] yet
+#[derive(Component, Debug)]
+pub struct AwaitingHello {
+    deadline: Duration,
+}
+
+/// Link that was told why it can't play and gets disconnected at `at`
+#[derive(Component, Debug)]
+pub struct Rejecting {
+    at: Duration,
+}
+
+/// Triggered once a link passed the handshake. Only then does it get a player and replication.
+#[derive(EntityEvent, Debug)]
+pub struct Accepted {
+    pub entity: Entity,
+}
+
+/// The host's own client runs the same build, only remote clients have to introduce themselves
+pub fn await_hello(
+    trigger: On<Add, Connected>,
+    links: Query<Has<HostClient>, With<ClientOf>>,
+    settings: Res<HandshakeSettings>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    match links.get(trigger.entity) {
+        Ok(true) => commands.trigger(Accepted {
+            entity: trigger.entity,
+        }),
+        Ok(false) => {
+            commands.entity(trigger.entity).insert(AwaitingHello {
+                deadline: time.elapsed() + settings.timeout,
+            });
+        }
+        Err(_) => {}
+    }
+}
+
+fn check(hello: &Hello) -> Result<(), RejectReason> {
+    let server_version = GAME_VERSION.to_string();
+    if !is_compatible(&hello.game_version, &server_version) {
+        Err(RejectReason::UpdateRequired { server_version })
+    } else if hello.protocol_id != protocol_id() {
+        Err(RejectReason::ProtocolMismatch)
+    } else {
+        Ok(())
+    }
+}
+
+fn reject(
+    link: Entity,
+    reason: RejectReason,
+    sender: &mut MessageSender<Rejected>,
+    settings: &HandshakeSettings,
+    now: Duration,
+    commands: &mut Commands,
+) {
+    warn!("Rejecting client {link:?}: {reason}");
+    sender.send::<HandshakeChannel>(Rejected(reason));
+    commands
+        .entity(link)
+        .remove::<AwaitingHello>()
+        .insert(Rejecting {
+            at: now + settings.linger,
+        });
+}
+
+pub fn receive_hello(
+    mut links: Query<
+        (
+            Entity,
+            &mut MessageReceiver<Hello>,
+            &mut MessageSender<Rejected>,
+        ),
+        (With<ClientOf>, With<AwaitingHello>),
+    >,
+    settings: Res<HandshakeSettings>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (link, mut receiver, mut sender) in &mut links {
+        let Some(hello) = receiver.receive().last() else {
+            continue;
+        };
+        match check(&hello) {
+            Ok(()) => {
+                info!("Client {link:?} runs version {}", hello.game_version);
+                commands
+                    .entity(link)
+                    .remove::<AwaitingHello>()
+                    .insert(ProfileClaim(hello.profile_token));
+                commands.trigger(Accepted { entity: link });
+            }
+            Err(reason) => reject(
+                link,
+                reason,
+                &mut sender,
+                &settings,
+                time.elapsed(),
+                &mut commands,
+            ),
+        }
+    }
+}
+
+/// Turns away clients that never said hello and disconnects the rejected ones
+pub fn expire_handshakes(
+    mut waiting: Query<(Entity, &AwaitingHello, &mut MessageSender<Rejected>)>,
+    rejecting: Query<(Entity, &Rejecting)>,
+    settings: Res<HandshakeSettings>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    for (link, awaiting, mut sender) in &mut waiting {
+        if now >= awaiting.deadline {
+            reject(
+                link,
+                RejectReason::NoHello,
+                &mut sender,
+                &settings,
+                now,
+                &mut commands,
+            );
+        }
+    }
+    for (link, rejecting) in &rejecting {
+        if now >= rejecting.at {
+            commands.entity(link).remove::<Rejecting>();
+            commands.trigger(Disconnect { entity: link });
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::combat::PositionHistory;
+use super::replay::ReplayRecorder;
+use super::teams::TeamSettings;
+use crate::protocol::{components::*, map::MapSettings, messages::*};
+use bevy::prelude::*;
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use std::time::Duration;
+
+#[derive(Resource, Clone, Debug)]
+pub struct HealthSettings {
+    /// How long dead players wait before they respawn
+    pub respawn_delay: Duration,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            respawn_delay: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Damage dealt to a player. Every source of damage goes through this message.
+#[derive(Message, Clone, Debug)]
+pub struct Damage {
+    pub target: Entity,
+    pub amount: u32,
+    /// The player that dealt the damage, if any
+    pub source: Option<Entity>,
+}
+
+/// A player's health dropped to zero
+#[derive(Message, Clone, Debug)]
+pub struct Died {
+    pub victim: Entity,
+    pub killer: Option<Entity>,
+}
+
+/// The dead player comes back at a spawn point once the server time reaches `at`
+#[derive(Component, Debug)]
+pub struct Respawning {
+    at: Duration,
+}
+
+pub fn apply_damage(
+    mut damages: MessageReader<Damage>,
+    mut players: Query<(&mut Health, &mut LifeState, &mut PlayerVelocity)>,
+    teams: Query<&Team>,
+    settings: Res<HealthSettings>,
+    team_settings: Res<TeamSettings>,
+    time: Res<Time>,
+    mut deaths: MessageWriter<Died>,
+    mut commands: Commands,
+) {
+    for damage in damages.read() {
+        let Ok((mut health, mut life, mut velocity)) = players.get_mut(damage.target) else {
+            continue;
+        };
+        if !life.is_alive() {
+            continue;
+        }
+        let source_team = damage.source.and_then(|source| teams.get(source).ok());
+        let amount = match (source_team, teams.get(damage.target).ok()) {
+            (Some(source), Some(target)) if source == target => {
+                team_settings.friendly_fire.scale(damage.amount)
+            }
+            _ => damage.amount,
+        };
+        if amount == 0 {
+            continue;
+        }
+        health.current = health.current.saturating_sub(amount);
+        if health.current > 0 {
+            continue;
+        }
+
+        *life = LifeState::Dead;
+        velocity.set_if_neq(PlayerVelocity::default());
+        commands.entity(damage.target).insert(Respawning {
+            at: time.elapsed() + settings.respawn_delay,
+        });
+        deaths.write(Died {
+            victim: damage.target,
+            killer: damage.source,
+        });
+    }
+}
+
+/// Tells the clients of the room about every death for their kill feed
+pub fn broadcast_kills(
+    mut deaths: MessageReader<Died>,
+    players: Query<(&PlayerId, &RoomId)>,
+    mut links: Query<(&RoomId, &mut MessageSender<PlayerKilled>), With<ClientOf>>,
+) {
+    for death in deaths.read() {
+        let Ok((victim, room)) = players.get(death.victim) else {
+            continue;
+        };
+        let killer = death
+            .killer
+            .and_then(|killer| players.get(killer).ok())
+            .map(|(killer, _)| killer.0);
+        info!("{:?} killed by {killer:?}", victim.0);
+        for (link_room, mut sender) in &mut links {
+            if link_room == room {
+                sender.send::<EventChannel>(PlayerKilled {
+                    victim: victim.0,
+                    killer,
+                });
+            }
+        }
+    }
+}
+
+/// Must run before the movement: the replay records the teleport ahead of the tick's inputs
+pub fn respawn(
+    mut dead: Query<(
+        Entity,
+        &PlayerId,
+        &RoomId,
+        &Respawning,
+        &mut PlayerPosition,
+        &mut Health,
+        &mut LifeState,
+        Option<&Team>,
+        Option<&mut PositionHistory>,
+    )>,
+    alive: Query<(&PlayerPosition, &RoomId), (With<PlayerId>, Without<Respawning>)>,
+    map: Res<MapSettings>,
+    time: Res<Time>,
+    mut recorder: Option<ResMut<ReplayRecorder>>,
+    mut commands: Commands,
+) {
+    for (entity, id, room, respawning, mut position, mut health, mut life, team, history) in
+        &mut dead
+    {
+        if time.elapsed() < respawning.at {
+            continue;
+        }
+        let others = alive
+            .iter()
+            .filter(|(_, other_room)| *other_room == room)
+            .map(|(other, _)| other.0);
+        position.0 = map.spawn_point(team.copied(), others);
+        *health = Health::full(health.max);
+        *life = LifeState::Alive;
+
+        if let Some(mut history) = history {
+            history.clear();
+        }
+        if let Some(recorder) = &mut recorder {
+            recorder.teleport(id, position.0);
+        }
+        commands.entity(entity).remove::<Respawning>();
+    }
+}
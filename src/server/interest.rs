@@ -0,0 +1,105 @@
+use crate::protocol::components::*;
+use bevy::{ecs::entity::EntityHashSet, prelude::*};
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+
+/// Area of interest used to decide which entities get replicated to a client
+#[derive(Resource, Clone, Debug)]
+pub struct InterestSettings {
+    /// Entities further than this from the client's player are not replicated to it.
+    /// `None` replicates the whole room.
+    pub radius: Option<f32>,
+    /// Extra distance an entity that is already visible may move away before it is hidden,
+    /// so that entities on the edge don't flicker in and out
+    pub hysteresis: f32,
+}
+
+impl Default for InterestSettings {
+    fn default() -> Self {
+        Self {
+            // a bit more than half the diagonal of the 480x270 view
+            radius: Some(320.0),
+            hysteresis: 48.0,
+        }
+    }
+}
+
+/// Entities currently replicated to this client link
+#[derive(Component, Default, Debug)]
+pub struct InterestSet {
+    visible: EntityHashSet,
+}
+
+impl InterestSet {
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.visible.iter().copied()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.visible.contains(&entity)
+    }
+}
+
+/// Where an entity is for the interest checks. Entities without a position are everywhere in
+/// their room.
+pub fn position_of(
+    player: Option<&PlayerPosition>,
+    object: Option<&ObjectPosition>,
+) -> Option<Vec2> {
+    player
+        .map(|position| position.0)
+        .or(object.map(|position| position.0))
+}
+
+/// Replicates an entity to a client only if it is in the client's room and, when it has a
+/// position, within the interest radius of the client's player.
+///
+/// Entities without a position are visible to the whole room.
+pub fn update_visibility(
+    settings: Res<InterestSettings>,
+    mut links: Query<(Entity, &RoomId, &mut InterestSet), With<ClientOf>>,
+    players: Query<(&ControlledBy, &PlayerPosition), With<PlayerId>>,
+    mut entities: Query<(
+        Entity,
+        &RoomId,
+        Option<&PlayerPosition>,
+        Option<&ObjectPosition>,
+        &mut NetworkVisibility,
+    )>,
+) {
+    for (link, link_room, mut interest) in &mut links {
+        interest.visible.retain(|entity| entities.contains(*entity));
+        let viewer = players
+            .iter()
+            .find(|(controlled_by, _)| controlled_by.owner == link)
+            .map(|(_, position)| position.0);
+
+        for (entity, room, player_position, object_position, mut visibility) in &mut entities {
+            let position = position_of(player_position, object_position);
+            let was_visible = interest.visible.contains(&entity);
+            let in_range = match (settings.radius, viewer, position) {
+                (Some(radius), Some(viewer), Some(position)) => {
+                    let radius = if was_visible {
+                        radius + settings.hysteresis
+                    } else {
+                        radius
+                    };
+                    viewer.distance(position) <= radius
+                }
+                _ => true,
+            };
+
+            let visible = room == link_room && in_range;
+            if visible == was_visible {
+                continue;
+            }
+            if visible {
+                visibility.gain_visibility(link);
+                interest.visible.insert(entity);
+            } else {
+                visibility.lose_visibility(link);
+                interest.visible.remove(&entity);
+            }
+        }
+    }
+}
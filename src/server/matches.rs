@@ -0,0 +1,292 @@
+use super::health::Respawning;
+use super::replication::ReplicationClass;
+use super::rooms::{LOBBY, Rooms};
+use crate::protocol::{components::*, matches::*, stats::PlayerStats};
+use bevy::prelude::*;
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use std::time::Duration;
+
+#[derive(Resource, Clone, Debug)]
+pub struct MatchSettings {
+    /// Players a room needs before the countdown starts
+    pub min_players: usize,
+    pub countdown: Duration,
+    pub round_duration: Duration,
+    /// How long the results stay up before the room waits for the next round
+    pub results_duration: Duration,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            min_players: 2,
+            countdown: Duration::from_secs(5),
+            round_duration: Duration::from_secs(180),
+            results_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+/// When the current timed state of a room's match ends
+#[derive(Component, Debug)]
+pub struct MatchTimer {
+    pub ends_at: Duration,
+}
+
+/// The round of a room ended with the time limit
+#[derive(Message, Clone, Debug)]
+pub struct MatchEnded {
+    pub room: RoomId,
+}
+
+/// The inputs of this player are ignored because its room's match doesn't allow them
+#[derive(Component, Debug)]
+pub struct Frozen;
+
+/// Gives every room but the lobby a match entity. Closing a room despawns it along with the
+/// room's other entities.
+pub fn spawn_matches(
+    rooms: Res<Rooms>,
+    matches: Query<&RoomId, With<MatchStatus>>,
+    mut commands: Commands,
+) {
+    for (room, _) in rooms.iter() {
+        if room == LOBBY || matches.iter().any(|match_room| *match_room == room) {
+            continue;
+        }
+        commands.spawn(match_entity(
+            room,
+            MatchStatus {
+                state: MatchState::WaitingForPlayers,
+                remaining_secs: 0,
+            },
+        ));
+    }
+}
+
+/// Components of the match entity of `room`
+pub fn match_entity(room: RoomId, status: MatchStatus) -> impl Bundle {
+    (
+        status,
+        room,
+        Replicate::to_clients(NetworkTarget::All),
+        ReplicationClass::Static,
+        NetworkVisibility::default(),
+    )
+}
+
+/// The state a match moves to and how long it lasts, if `state` ends with `player_count`
+/// players in the room
+fn next_state(
+    state: MatchState,
+    player_count: usize,
+    time_up: bool,
+    settings: &MatchSettings,
+) -> Option<(MatchState, Option<Duration>)> {
+    match state {
+        MatchState::WaitingForPlayers if player_count >= settings.min_players => {
+            Some((MatchState::Countdown, Some(settings.countdown)))
+        }
+        MatchState::Countdown if player_count < settings.min_players => {
+            Some((MatchState::WaitingForPlayers, None))
+        }
+        MatchState::Countdown if time_up => {
+            Some((MatchState::InProgress, Some(settings.round_duration)))
+        }
+        MatchState::InProgress if player_count == 0 => Some((MatchState::WaitingForPlayers, None)),
+        MatchState::InProgress if time_up => {
+            Some((MatchState::Results, Some(settings.results_duration)))
+        }
+        MatchState::Results if time_up => Some((MatchState::WaitingForPlayers, None)),
+        _ => None,
+    }
+}
+
+/// Moves every match through waiting, countdown, round and results
+pub fn update_matches(
+    mut matches: Query<(Entity, &RoomId, &mut MatchStatus, Option<&MatchTimer>)>,
+    links: Query<&RoomId, With<ClientOf>>,
+    mut players: Query<
+        (
+            Entity,
+            &RoomId,
+            &mut Health,
+            &mut LifeState,
+            &mut PlayerStats,
+        ),
+        With<PlayerId>,
+    >,
+    settings: Res<MatchSettings>,
+    time: Res<Time>,
+    mut ended: MessageWriter<MatchEnded>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    for (entity, room, mut status, timer) in &mut matches {
+        let player_count = links.iter().filter(|link_room| *link_room == room).count();
+        let time_up = timer.is_some_and(|timer| now >= timer.ends_at);
+
+        let ends_at = match next_state(status.state, player_count, time_up, &settings) {
+            Some((state, duration)) => {
+                info!("Room {:?}: {:?} -> {state:?}", room, status.state);
+                if state == MatchState::InProgress {
+                    // every round starts with everyone alive, healthy and without score
+                    for (player, player_room, mut health, mut life, mut stats) in &mut players {
+                        if player_room == room {
+                            *health = Health::full(health.max);
+                            *life = LifeState::Alive;
+                            *stats = PlayerStats::default();
+                            commands.entity(player).remove::<Respawning>();
+                        }
+                    }
+                }
+                if state == MatchState::Results {
+                    ended.write(MatchEnded { room: *room });
+                }
+                let ends_at = duration.map(|duration| now + duration);
+                match ends_at {
+                    Some(ends_at) => commands.entity(entity).insert(MatchTimer { ends_at }),
+                    None => commands.entity(entity).remove::<MatchTimer>(),
+                };
+                status.state = state;
+                ends_at
+            }
+            None => timer.map(|timer| timer.ends_at),
+        };
+
+        // only touch the status when the displayed second changes, it is replicated
+        let remaining_secs = ends_at.map_or(0, |ends_at| {
+            ends_at.saturating_sub(now).as_secs_f32().ceil() as u16
+        });
+        if status.remaining_secs != remaining_secs {
+            status.remaining_secs = remaining_secs;
+        }
+    }
+}
+
+/// Freezes the players of rooms whose match doesn't allow inputs, see [`MatchState::allows_input`].
+/// Players in the lobby are never frozen.
+pub fn freeze_players(
+    matches: Query<(&RoomId, &MatchStatus)>,
+    players: Query<(Entity, &RoomId, Has<Frozen>), With<PlayerId>>,
+    mut commands: Commands,
+) {
+    for (player, room, frozen) in &players {
+        let allowed = *room == LOBBY
+            || matches
+                .iter()
+                .find(|(match_room, _)| *match_room == room)
+                .is_none_or(|(_, status)| status.state.allows_input());
+        if allowed && frozen {
+            commands.entity(player).remove::<Frozen>();
+        } else if !allowed && !frozen {
+            commands.entity(player).insert(Frozen);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn waiting() -> MatchStatus {
+        MatchStatus {
+            state: MatchState::WaitingForPlayers,
+            remaining_secs: 0,
+        }
+    }
+
+    #[test]
+    fn countdown_needs_the_minimum_players() {
+        let settings = MatchSettings::default();
+        let min = settings.min_players;
+        assert_eq!(
+            next_state(MatchState::WaitingForPlayers, min - 1, false, &settings),
+            None
+        );
+        assert_eq!(
+            next_state(MatchState::WaitingForPlayers, min, false, &settings),
+            Some((MatchState::Countdown, Some(settings.countdown)))
+        );
+    }
+
+    #[test]
+    fn countdown_aborts_when_players_leave() {
+        let settings = MatchSettings::default();
+        let min = settings.min_players;
+        assert_eq!(
+            next_state(MatchState::Countdown, min, false, &settings),
+            None
+        );
+        // leaving wins over the countdown running out on the same tick
+        assert_eq!(
+            next_state(MatchState::Countdown, min - 1, true, &settings),
+            Some((MatchState::WaitingForPlayers, None))
+        );
+    }
+
+    #[test]
+    fn time_up_moves_the_round_along() {
+        let settings = MatchSettings::default();
+        let min = settings.min_players;
+        assert_eq!(
+            next_state(MatchState::Countdown, min, true, &settings),
+            Some((MatchState::InProgress, Some(settings.round_duration)))
+        );
+        assert_eq!(
+            next_state(MatchState::InProgress, 1, false, &settings),
+            None
+        );
+        assert_eq!(
+            next_state(MatchState::InProgress, 1, true, &settings),
+            Some((MatchState::Results, Some(settings.results_duration)))
+        );
+        // the results stay up even if everyone left
+        assert_eq!(next_state(MatchState::Results, 0, false, &settings), None);
+        assert_eq!(
+            next_state(MatchState::Results, 0, true, &settings),
+            Some((MatchState::WaitingForPlayers, None))
+        );
+    }
+
+    #[test]
+    fn an_empty_room_ends_its_round() {
+        let settings = MatchSettings::default();
+        assert_eq!(
+            next_state(MatchState::InProgress, 0, false, &settings),
+            Some((MatchState::WaitingForPlayers, None))
+        );
+        assert_eq!(
+            next_state(MatchState::WaitingForPlayers, 0, true, &settings),
+            None
+        );
+    }
+
+    #[test]
+    fn the_lobby_has_no_match() {
+        let mut world = World::new();
+        world.init_resource::<Rooms>();
+        world.run_system_once(spawn_matches).unwrap();
+        let mut matches = world.query::<&MatchStatus>();
+        assert_eq!(matches.iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn lobby_players_are_never_frozen() {
+        let mut world = World::new();
+        let room = RoomId(1);
+        world.spawn((room, waiting()));
+        world.spawn((
+            LOBBY,
+            MatchStatus {
+                state: MatchState::Countdown,
+                remaining_secs: 3,
+            },
+        ));
+        let lobby_player = world.spawn((PlayerId(PeerId::Netcode(1)), LOBBY)).id();
+        world.run_system_once(freeze_players).unwrap();
+        assert!(!world.entity(lobby_player).contains::<Frozen>());
+    }
+}
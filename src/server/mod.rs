@@ -1,2 +1,19 @@
+pub mod combat;
+pub mod console;
+pub mod handshake;
+pub mod health;
+pub mod interest;
+pub mod matches;
+pub mod npcs;
+pub mod pickups;
 pub mod plugin;
+pub mod profiles;
+pub mod projectiles;
+pub mod replay;
+pub mod replication;
+pub mod rooms;
+pub mod snapshot;
+pub mod stats;
+pub mod teams;
 pub mod updates;
+pub mod validation;
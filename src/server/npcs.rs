@@ -0,0 +1,207 @@
+use super::replication::ReplicationClass;
+use super::rooms::Rooms;
+use crate::protocol::random::GameRng;
+use crate::protocol::{components::*, map::MapSettings, quantize};
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Designer editable NPC definitions, read when the server starts
+pub const NPC_FILE: &str = "assets/npcs.json";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Behaviour {
+    /// Walks in a random direction, changing every `wander_ticks`
+    Wander,
+    /// Walks towards the nearest player in sight, wanders otherwise
+    Chase,
+    /// Walks away from the nearest player in sight, wanders otherwise
+    Flee,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct NpcDefinition {
+    pub id: String,
+    pub behaviour: Behaviour,
+    pub appearance: PlayerAppearance,
+    /// How many of these live in every room
+    pub count: usize,
+    /// Where they spawn
+    pub spawn: [f32; 2],
+    /// Radius of the circle around `spawn` they are spread over, so that they don't stack
+    #[serde(default)]
+    pub spawn_radius: f32,
+    /// Distance at which they notice players
+    pub sight_range: f32,
+    /// Ticks between two changes of the wandering direction
+    pub wander_ticks: u16,
+}
+
+impl NpcDefinition {
+    /// Where the `slot`th of the `count` NPCs spawns: evenly around the spawn circle, or in
+    /// its center when a wall is in the way
+    pub fn spawn_point(&self, slot: usize, map: &MapSettings) -> Vec2 {
+        let center = Vec2::from(self.spawn);
+        let angle = slot as f32 / self.count.max(1) as f32 * std::f32::consts::TAU;
+        let position = quantize::snap(center + Vec2::from_angle(angle) * self.spawn_radius);
+        if map.blocks(position) {
+            center
+        } else {
+            position
+        }
+    }
+}
+
+#[derive(Resource, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NpcCatalog {
+    pub npcs: Vec<NpcDefinition>,
+}
+
+impl NpcCatalog {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }
+}
+
+/// Server side state of an NPC
+#[derive(Component, Debug)]
+pub struct NpcBrain {
+    /// Index of the definition in the [`NpcCatalog`]
+    definition: usize,
+    /// Direction picked by the last wandering roll
+    wander: Vec2,
+    wander_ticks_left: u16,
+}
+
+/// What the AI decided this tick, applied by [`npc_movement`] like client inputs
+#[derive(Component, Default, Debug)]
+pub struct NpcInputs(Inputs);
+
+pub fn load_npcs(mut commands: Commands) {
+    let catalog = NpcCatalog::load(Path::new(NPC_FILE)).unwrap_or_else(|e| {
+        error!("Could not load {NPC_FILE}, no NPCs will spawn: {e}");
+        NpcCatalog::default()
+    });
+    commands.insert_resource(catalog);
+}
+
+/// Keeps every room populated with the NPCs of the catalog
+pub fn spawn_npcs(
+    rooms: Res<Rooms>,
+    catalog: Res<NpcCatalog>,
+    npcs: Query<(&RoomId, &NpcBrain)>,
+    map: Res<MapSettings>,
+    mut commands: Commands,
+) {
+    for (room, _) in rooms.iter() {
+        for (index, definition) in catalog.npcs.iter().enumerate() {
+            let alive = npcs
+                .iter()
+                .filter(|(npc_room, brain)| **npc_room == room && brain.definition == index)
+                .count();
+            for slot in alive..definition.count {
+                let position = definition.spawn_point(slot, &map);
+                commands.spawn(npc(&catalog, index, room, position));
+            }
+        }
+    }
+}
+
+/// Components of an NPC of the catalog's definition `index`
+pub fn npc(catalog: &NpcCatalog, index: usize, room: RoomId, position: Vec2) -> impl Bundle {
+    let definition = &catalog.npcs[index];
+    (
+        Npc {
+            kind: definition.id.clone(),
+        },
+        NpcBrain {
+            definition: index,
+            wander: Vec2::ZERO,
+            wander_ticks_left: 0,
+        },
+        NpcInputs::default(),
+        PlayerPosition(position),
+        PlayerVelocity::default(),
+        definition.appearance.clone(),
+        room,
+        Replicate::to_clients(NetworkTarget::All),
+        ReplicationClass::Npc,
+        NetworkVisibility::default(),
+        // remote players are interpolated, NPCs are drawn the same way
+        InterpolationTarget::to_clients(NetworkTarget::All),
+    )
+}
+
+/// Picks the direction every NPC wants to walk in this tick
+pub fn npc_brains(
+    mut npcs: Query<(&mut NpcBrain, &mut NpcInputs, &PlayerPosition, &RoomId)>,
+    players: Query<(&PlayerPosition, &RoomId, &LifeState), With<PlayerId>>,
+    catalog: Res<NpcCatalog>,
+    map: Res<MapSettings>,
+    mut rng: ResMut<GameRng>,
+) {
+    for (mut brain, mut inputs, position, room) in &mut npcs {
+        let Some(definition) = catalog.npcs.get(brain.definition) else {
+            continue;
+        };
+        let nearest = players
+            .iter()
+            .filter(|(_, player_room, life)| *player_room == room && life.is_alive())
+            .map(|(player, ..)| player.0 - position.0)
+            .filter(|offset| offset.length() <= definition.sight_range)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+
+        let heading = match (definition.behaviour, nearest) {
+            (Behaviour::Chase, Some(offset)) => offset,
+            (Behaviour::Flee, Some(offset)) => -offset,
+            _ => {
+                if brain.wander_ticks_left == 0 {
+                    brain.wander_ticks_left = definition.wander_ticks.max(1);
+                    brain.wander = random_heading(&mut rng);
+                }
+                brain.wander_ticks_left -= 1;
+                brain.wander
+            }
+        };
+        // never walk off the map, head back to its center instead
+        let margin = map.bounds.inflate(-16.0);
+        let heading = if margin.contains(position.0) {
+            heading
+        } else {
+            margin.center() - position.0
+        };
+        inputs.0 = Inputs::Direction(direction_towards(heading));
+    }
+}
+
+/// Moves the NPCs with the same code that moves the players
+pub fn npc_movement(mut npcs: Query<(&mut PlayerPosition, &mut PlayerVelocity, &NpcInputs)>) {
+    for (position, velocity, inputs) in &mut npcs {
+        shared_movement_behaviour(position, velocity, &inputs.0, false);
+    }
+}
+
+/// A random direction, or standing still one time in five
+fn random_heading(rng: &mut GameRng) -> Vec2 {
+    if rng.random_ratio(1, 5) {
+        return Vec2::ZERO;
+    }
+    Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU))
+}
+
+/// The eight way [`Direction`] closest to `heading`
+fn direction_towards(heading: Vec2) -> Direction {
+    let heading = heading.normalize_or_zero();
+    // 22.5° either side of an axis still counts as walking along it
+    let threshold = (std::f32::consts::PI / 8.0).sin();
+    Direction {
+        up: heading.y > threshold,
+        down: heading.y < -threshold,
+        left: heading.x < -threshold,
+        right: heading.x > threshold,
+    }
+}
@@ -0,0 +1,206 @@
+use super::profiles::ProfileKey;
+use super::replication::ReplicationClass;
+use super::rooms::Rooms;
+use super::snapshot::RestoredPlayers;
+use crate::protocol::random::GameRng;
+use crate::protocol::{components::*, map::MapSettings, pickups::*};
+use bevy::{platform::collections::HashMap, prelude::*};
+use lightyear::prelude::server::Server;
+use lightyear::prelude::*;
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Resource, Clone, Debug)]
+pub struct PickupSettings {
+    /// Distance at which a player collects a pickup
+    pub radius: f32,
+    /// Health restored by a health pickup
+    pub heal: u32,
+    pub speed_boost_ticks: u16,
+    pub coin_value: u32,
+}
+
+impl Default for PickupSettings {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            heal: 30,
+            // 5 seconds at 64 ticks per second
+            speed_boost_ticks: 320,
+            coin_value: 1,
+        }
+    }
+}
+
+/// When each spawner of each room may spawn again, spawners without an entry spawn right away
+#[derive(Resource, Default, Debug)]
+pub struct PickupSpawns {
+    ready_at: HashMap<(RoomId, usize), Duration>,
+}
+
+/// Index of the [`MapSettings::pickups`] spawner a pickup came from
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FromSpawner(pub usize);
+
+/// A player collected a pickup
+#[derive(Message, Clone, Debug)]
+pub struct Collected {
+    pub player: Entity,
+    pub kind: PickupKind,
+}
+
+/// Gives every player an inventory on its own entity, replicated only to the owning client.
+/// Players of a restored snapshot get back the inventory they had.
+pub fn spawn_inventory(
+    trigger: On<Add, PlayerId>,
+    players: Query<(&PlayerId, &ControlledBy, Option<&ProfileKey>)>,
+    mut restored: ResMut<RestoredPlayers>,
+    mut commands: Commands,
+) {
+    let Ok((id, controlled_by, key)) = players.get(trigger.entity) else {
+        return;
+    };
+    let inventory = key
+        .and_then(|key| restored.0.remove(key))
+        .map(|saved| saved.inventory)
+        .unwrap_or_default();
+    commands.spawn((
+        inventory,
+        Replicate::to_clients(NetworkTarget::Single(id.0)),
+        ReplicationClass::Static,
+        // despawned with the client's link
+        ControlledBy {
+            owner: controlled_by.owner,
+            lifetime: Default::default(),
+        },
+    ));
+}
+
+/// Fills every empty spawner of every room once its respawn delay is over
+pub fn spawn_pickups(
+    rooms: Res<Rooms>,
+    map: Res<MapSettings>,
+    mut spawns: ResMut<PickupSpawns>,
+    pickups: Query<(&RoomId, &FromSpawner)>,
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mut commands: Commands,
+) {
+    spawns
+        .ready_at
+        .retain(|(room, _), _| rooms.get(*room).is_some());
+
+    let now = time.elapsed();
+    for (room, _) in rooms.iter() {
+        for (index, spawner) in map.pickups.iter().enumerate() {
+            let occupied = pickups
+                .iter()
+                .any(|(pickup_room, from)| *pickup_room == room && from.0 == index);
+            let waiting = spawns
+                .ready_at
+                .get(&(room, index))
+                .is_some_and(|ready_at| now < *ready_at);
+            if occupied || waiting || spawner.kinds.is_empty() {
+                continue;
+            }
+
+            let kind = spawner.kinds[rng.random_range(0..spawner.kinds.len())];
+            commands.spawn(pickup(kind, &map, index, room));
+        }
+    }
+}
+
+/// Components of a pickup lying on the map's spawner `index`
+pub fn pickup(kind: PickupKind, map: &MapSettings, index: usize, room: RoomId) -> impl Bundle {
+    (
+        Pickup(kind),
+        ObjectPosition(map.pickups[index].position),
+        room,
+        FromSpawner(index),
+        Replicate::to_clients(NetworkTarget::All),
+        ReplicationClass::Static,
+        NetworkVisibility::default(),
+    )
+}
+
+/// Must run after the movement. The server alone decides who collects what.
+pub fn collect_pickups(
+    pickups: Query<(Entity, &Pickup, &ObjectPosition, &RoomId, &FromSpawner)>,
+    mut players: Query<
+        (
+            Entity,
+            &PlayerPosition,
+            &RoomId,
+            &LifeState,
+            &ControlledBy,
+            &mut Health,
+        ),
+        With<PlayerId>,
+    >,
+    mut inventories: Query<(&ControlledBy, &mut Inventory)>,
+    settings: Res<PickupSettings>,
+    map: Res<MapSettings>,
+    mut spawns: ResMut<PickupSpawns>,
+    server: Single<&LocalTimeline, With<Server>>,
+    time: Res<Time>,
+    mut collected: MessageWriter<Collected>,
+    mut commands: Commands,
+) {
+    for (pickup, kind, position, room, from) in &pickups {
+        let Some((player, _, _, _, controlled_by, mut health)) =
+            players
+                .iter_mut()
+                .find(|(_, player, player_room, life, ..)| {
+                    *player_room == room
+                        && life.is_alive()
+                        && player.distance(position.0) <= settings.radius
+                })
+        else {
+            continue;
+        };
+
+        match kind.0 {
+            PickupKind::Health => {
+                health.current = (health.current + settings.heal).min(health.max);
+            }
+            PickupKind::SpeedBoost => {
+                commands.entity(player).insert(SpeedBoost {
+                    until: server.tick().0.wrapping_add(settings.speed_boost_ticks),
+                });
+            }
+            PickupKind::Coin => {
+                if let Some((_, mut inventory)) = inventories
+                    .iter_mut()
+                    .find(|(owner, _)| owner.owner == controlled_by.owner)
+                {
+                    inventory.coins += settings.coin_value;
+                }
+            }
+        }
+
+        if let Some(spawner) = map.pickups.get(from.0) {
+            spawns
+                .ready_at
+                .insert((*room, from.0), time.elapsed() + spawner.respawn_after);
+        }
+        collected.write(Collected {
+            player,
+            kind: kind.0,
+        });
+        commands.entity(pickup).despawn();
+    }
+}
+
+/// Removes boosts that wore off, they would otherwise turn active again once the tick wraps
+pub fn expire_speed_boosts(
+    boosts: Query<(Entity, &SpeedBoost)>,
+    server: Single<&LocalTimeline, With<Server>>,
+    mut commands: Commands,
+) {
+    let tick = server.tick().0;
+    for (entity, boost) in &boosts {
+        if !boost.is_active(tick) {
+            commands.entity(entity).remove::<SpeedBoost>();
+        }
+    }
+}
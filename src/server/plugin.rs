@@ -1,15 +1,138 @@
 use super::*;
+use crate::protocol::{conditioner::ConditionerPlugin, plugin::ProtocolPlugin, random::GameRng};
 use bevy::prelude::*;
 
 pub struct ServerPlugin;
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(crate::protocol::plugin::ProtocolPlugin);
+        // the host runs both plugins in one app, so only the first one registers the protocol
+        if !app.is_plugin_added::<ProtocolPlugin>() {
+            app.add_plugins(ProtocolPlugin);
+        }
+        if !app.is_plugin_added::<ConditionerPlugin>() {
+            app.add_plugins(ConditionerPlugin);
+        }
 
+        app.init_resource::<replication::ReplicationSettings>();
+        app.add_observer(replication::apply_class);
         app.add_observer(updates::handle_new_client);
         app.add_observer(updates::handle_connected);
 
-        app.add_systems(FixedUpdate, updates::movement);
+        app.init_resource::<handshake::HandshakeSettings>();
+        app.add_observer(handshake::await_hello);
+        app.add_systems(
+            Update,
+            (handshake::receive_hello, handshake::expire_handshakes).chain(),
+        );
+
+        app.add_systems(Startup, replay::start_recording);
+        app.add_observer(replay::record_player_spawn);
+        app.add_observer(replay::record_player_despawn);
+
+        app.init_resource::<validation::ValidationPolicy>();
+        app.init_resource::<validation::AuditLog>();
+        app.add_message::<validation::InputFlagged>();
+        app.add_systems(Startup, validation::open_audit_log);
+        app.add_observer(validation::track_new_player);
+
+        app.init_resource::<combat::CombatSettings>();
+        app.add_message::<combat::Hit>();
+        app.add_observer(combat::track_new_player);
+        app.add_observer(projectiles::track_new_player);
+
+        app.init_resource::<GameRng>();
+        app.init_resource::<pickups::PickupSettings>();
+        app.init_resource::<pickups::PickupSpawns>();
+        app.add_message::<pickups::Collected>();
+        app.add_observer(pickups::spawn_inventory);
+
+        app.init_resource::<matches::MatchSettings>();
+        app.add_message::<matches::MatchEnded>();
+
+        app.init_resource::<teams::TeamSettings>();
+
+        app.init_resource::<snapshot::SnapshotSettings>();
+        app.init_resource::<snapshot::RestoredPlayers>();
+        app.add_systems(
+            Startup,
+            (npcs::load_npcs, snapshot::restore_snapshot).chain(),
+        );
+        app.add_systems(Last, snapshot::save_snapshot);
+
+        app.init_resource::<profiles::ProfileSettings>();
+        app.add_systems(Startup, profiles::open_profiles);
+        app.add_observer(profiles::save_on_disconnect);
+
+        app.init_resource::<health::HealthSettings>();
+        app.add_message::<health::Damage>();
+        app.add_message::<health::Died>();
+
+        app.init_resource::<rooms::Rooms>();
+        app.init_resource::<interest::InterestSettings>();
+        app.add_message::<console::ConsoleCommand>();
+        app.add_systems(Startup, console::start_console);
+        app.add_systems(
+            Update,
+            (
+                console::read_console,
+                rooms::handle_console_commands,
+                rooms::handle_room_commands,
+                rooms::broadcast_room_list,
+                updates::select_appearance,
+                teams::select_team,
+                teams::balance_on_room_change,
+                pickups::spawn_pickups,
+                matches::spawn_matches,
+                npcs::spawn_npcs,
+                matches::update_matches,
+                stats::write_results,
+                stats::broadcast_standings,
+                profiles::save_profiles,
+                interest::update_visibility,
+                replication::prioritize_per_viewer,
+            )
+                .chain(),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                (
+                    validation::check_inputs,
+                    validation::check_input_timing,
+                    validation::check_speed,
+                    health::respawn,
+                    matches::freeze_players,
+                ),
+                (
+                    updates::movement,
+                    (npcs::npc_brains, npcs::npc_movement).chain(),
+                ),
+                (replay::record_tick, pickups::collect_pickups),
+                (
+                    combat::record_history,
+                    combat::resolve_melee,
+                    combat::broadcast_hits,
+                    projectiles::fire_projectiles,
+                    projectiles::move_projectiles,
+                    projectiles::projectile_hits,
+                    health::apply_damage,
+                    (
+                        health::broadcast_kills,
+                        stats::count_kills,
+                        stats::count_pickups,
+                        profiles::count_lifetime_stats,
+                    ),
+                )
+                    .chain(),
+                (
+                    validation::apply_policy,
+                    validation::expire_throttles,
+                    pickups::expire_speed_boosts,
+                ),
+            )
+                .chain(),
+        );
     }
 }
@@ -0,0 +1,437 @@
+use super::health::Died;
+use super::pickups::Collected;
+use crate::protocol::components::*;
+use bevy::prelude::*;
+use lightyear::prelude::PeerId;
+use rand::Rng;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Schema changes, applied in order. `PRAGMA user_version` holds how many of them already
+/// ran on a database, so a release only ever appends to this list.
+const MIGRATIONS: &[&str] = &["CREATE TABLE profiles (
+        key TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        character TEXT NOT NULL,
+        palette INTEGER NOT NULL,
+        kills INTEGER NOT NULL DEFAULT 0,
+        deaths INTEGER NOT NULL DEFAULT 0,
+        pickups INTEGER NOT NULL DEFAULT 0,
+        last_x REAL,
+        last_y REAL,
+        updated_at INTEGER NOT NULL
+    );"];
+
+#[derive(Resource, Clone, Debug)]
+pub struct ProfileSettings {
+    pub database: PathBuf,
+    /// How often the profiles of the connected players are written back
+    pub save_interval: Duration,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Self {
+            database: PathBuf::from("./data/profiles.sqlite"),
+            save_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Counters over every match a player ever played
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LifetimeStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub pickups: u32,
+}
+
+/// Name the profile of a player is stored under
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct ProfileName(pub String);
+
+/// Key the profile of a player is stored under: the hash of the secret token the server
+/// issued to its client, so that the database never holds the tokens themselves. Guests,
+/// whose profile is never saved, have none.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProfileKey(pub String);
+
+impl ProfileKey {
+    /// The host's own client plays on the server's machine and needs no token
+    pub fn host() -> Self {
+        Self("host".to_string())
+    }
+
+    pub fn of(token: &str) -> Self {
+        Self(format!("{:x}", sha2::Sha256::digest(token.as_bytes())))
+    }
+}
+
+/// The token a client sent in its [`Hello`](crate::protocol::messages::Hello), kept on its
+/// link until the player is spawned
+#[derive(Component, Clone, Debug)]
+pub struct ProfileClaim(pub Option<String>);
+
+/// The profile key of a new player, and the token to send back if a new one was issued.
+///
+/// Clients without a token get a new one. A token that is already playing gets a guest, so
+/// that two sessions never overwrite each other's profile.
+pub fn claim(
+    token: Option<&str>,
+    host: bool,
+    in_use: impl Fn(&ProfileKey) -> bool,
+) -> (Option<ProfileKey>, Option<String>) {
+    if host {
+        return (Some(ProfileKey::host()), None);
+    }
+    match token {
+        Some(token) => {
+            let key = ProfileKey::of(token);
+            if in_use(&key) {
+                warn!("Profile {} is already playing, joining as a guest", key.0);
+                (None, None)
+            } else {
+                (Some(key), None)
+            }
+        }
+        None => {
+            let token: String = rand::rng()
+                .random::<[u8; 16]>()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            (Some(ProfileKey::of(&token)), Some(token))
+        }
+    }
+}
+
+/// Everything the server remembers about a player between sessions
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub appearance: PlayerAppearance,
+    pub lifetime: LifetimeStats,
+    pub last_position: Option<Vec2>,
+}
+
+impl Profile {
+    pub fn new(peer: PeerId, appearance: PlayerAppearance) -> Self {
+        Self {
+            name: format!("player {}", peer.to_bits()),
+            appearance,
+            lifetime: LifetimeStats::default(),
+            last_position: None,
+        }
+    }
+}
+
+/// Player profiles in an embedded SQLite database, keyed by [`ProfileKey`]
+#[derive(Resource)]
+pub struct ProfileStore {
+    connection: Mutex<Connection>,
+}
+
+impl ProfileStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(directory) = path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        let mut connection = Connection::open(path)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn load(&self, key: &ProfileKey) -> rusqlite::Result<Option<Profile>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT name, character, palette, kills, deaths, pickups, last_x, last_y
+                 FROM profiles WHERE key = ?1",
+                params![key.0],
+                |row| {
+                    let last_x: Option<f32> = row.get(6)?;
+                    let last_y: Option<f32> = row.get(7)?;
+                    Ok(Profile {
+                        name: row.get(0)?,
+                        appearance: PlayerAppearance {
+                            character: row.get(1)?,
+                            palette: row.get(2)?,
+                        },
+                        lifetime: LifetimeStats {
+                            kills: row.get(3)?,
+                            deaths: row.get(4)?,
+                            pickups: row.get(5)?,
+                        },
+                        last_position: last_x.zip(last_y).map(|(x, y)| Vec2::new(x, y)),
+                    })
+                },
+            )
+            .optional()
+    }
+
+    /// Writes all `profiles` in one transaction
+    pub fn save(&self, profiles: &[(ProfileKey, Profile)]) -> rusqlite::Result<()> {
+        let updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs() as i64;
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for (key, profile) in profiles {
+            transaction.execute(
+                "INSERT INTO profiles
+                     (key, name, character, palette, kills, deaths, pickups, last_x, last_y, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT(key) DO UPDATE SET
+                     name = excluded.name,
+                     character = excluded.character,
+                     palette = excluded.palette,
+                     kills = excluded.kills,
+                     deaths = excluded.deaths,
+                     pickups = excluded.pickups,
+                     last_x = excluded.last_x,
+                     last_y = excluded.last_y,
+                     updated_at = excluded.updated_at",
+                params![
+                    key.0,
+                    profile.name,
+                    profile.appearance.character,
+                    profile.appearance.palette,
+                    profile.lifetime.kills,
+                    profile.lifetime.deaths,
+                    profile.lifetime.pickups,
+                    profile.last_position.map(|position| position.x),
+                    profile.last_position.map(|position| position.y),
+                    updated_at,
+                ],
+            )?;
+        }
+        transaction.commit()
+    }
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let applied: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index as i64 + 1)?;
+        transaction.commit()?;
+        info!("Migrated the profile database to version {}", index + 1);
+    }
+    Ok(())
+}
+
+/// Without a database the server still runs, it just forgets everyone on restart
+pub fn open_profiles(mut commands: Commands, settings: Res<ProfileSettings>) {
+    match ProfileStore::open(&settings.database) {
+        Ok(store) => commands.insert_resource(store),
+        Err(e) => error!(
+            "Could not open the profile database {}: {e}",
+            settings.database.display()
+        ),
+    }
+}
+
+type ProfileData<'a> = (
+    &'a ProfileKey,
+    &'a ProfileName,
+    &'a PlayerAppearance,
+    &'a LifetimeStats,
+    &'a PlayerPosition,
+);
+
+fn profile_of((key, name, appearance, lifetime, position): ProfileData) -> (ProfileKey, Profile) {
+    (
+        key.clone(),
+        Profile {
+            name: name.0.clone(),
+            appearance: appearance.clone(),
+            lifetime: *lifetime,
+            last_position: Some(position.0),
+        },
+    )
+}
+
+pub fn count_lifetime_stats(
+    mut deaths: MessageReader<Died>,
+    mut collected: MessageReader<Collected>,
+    mut players: Query<&mut LifetimeStats>,
+) {
+    for death in deaths.read() {
+        if let Ok(mut victim) = players.get_mut(death.victim) {
+            victim.deaths += 1;
+        }
+        if let Some(Ok(mut killer)) = death.killer.map(|killer| players.get_mut(killer)) {
+            killer.kills += 1;
+        }
+    }
+    for collected in collected.read() {
+        if let Ok(mut stats) = players.get_mut(collected.player) {
+            stats.pickups += 1;
+        }
+    }
+}
+
+pub fn save_profiles(
+    players: Query<ProfileData>,
+    store: Option<Res<ProfileStore>>,
+    settings: Res<ProfileSettings>,
+    time: Res<Time>,
+    mut last_save: Local<Duration>,
+) {
+    let Some(store) = store else {
+        return;
+    };
+    if time.elapsed() - *last_save < settings.save_interval {
+        return;
+    }
+    *last_save = time.elapsed();
+
+    let profiles: Vec<_> = players.iter().map(profile_of).collect();
+    if let Err(e) = store.save(&profiles) {
+        error!("Could not save {} profiles: {e}", profiles.len());
+    }
+}
+
+/// The player entity is despawned with its client's link
+pub fn save_on_disconnect(
+    trigger: On<Remove, PlayerId>,
+    players: Query<ProfileData>,
+    store: Option<Res<ProfileStore>>,
+) {
+    let (Some(store), Ok(player)) = (store, players.get(trigger.entity)) else {
+        return;
+    };
+    let profile = profile_of(player);
+    if let Err(e) = store.save(&[profile]) {
+        error!("Could not save the profile of {:?}: {e}", trigger.entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database file for one test
+    fn database(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("profiles-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory.join("profiles.sqlite")
+    }
+
+    fn user_version(connection: &Connection) -> i64 {
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "ada".to_string(),
+            appearance: PlayerAppearance {
+                character: "elf".to_string(),
+                palette: 3,
+            },
+            lifetime: LifetimeStats {
+                kills: 4,
+                deaths: 2,
+                pickups: 9,
+            },
+            last_position: Some(Vec2::new(-12.5, 40.0)),
+        }
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let path = database("migrate");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut connection = Connection::open(&path).unwrap();
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+        drop(connection);
+
+        // reopening must not run `CREATE TABLE` again
+        let mut connection = Connection::open(&path).unwrap();
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn profiles_survive_a_reopen() {
+        let path = database("round-trip");
+        let key = ProfileKey::of("secret");
+        let store = ProfileStore::open(&path).unwrap();
+        assert_eq!(store.load(&key).unwrap(), None);
+        store.save(&[(key.clone(), profile())]).unwrap();
+        drop(store);
+
+        let store = ProfileStore::open(&path).unwrap();
+        assert_eq!(store.load(&key).unwrap(), Some(profile()));
+
+        // saving again updates the row
+        let mut moved = profile();
+        moved.lifetime.kills += 1;
+        moved.last_position = None;
+        store.save(&[(key.clone(), moved.clone())]).unwrap();
+        assert_eq!(store.load(&key).unwrap(), Some(moved));
+        assert_eq!(store.load(&ProfileKey::of("other")).unwrap(), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn clients_without_a_token_get_a_new_one() {
+        let (key, token) = claim(None, false, |_| false);
+        let token = token.expect("a new token");
+        assert_eq!(token.len(), 32);
+        assert_eq!(key, Some(ProfileKey::of(&token)));
+        assert_ne!(claim(None, false, |_| false).1, Some(token));
+    }
+
+    #[test]
+    fn known_tokens_get_their_profile() {
+        assert_eq!(
+            claim(Some("secret"), false, |_| false),
+            (Some(ProfileKey::of("secret")), None)
+        );
+    }
+
+    #[test]
+    fn a_token_already_playing_gets_a_guest() {
+        let playing = ProfileKey::of("secret");
+        assert_eq!(
+            claim(Some("secret"), false, |key| *key == playing),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn the_host_needs_no_token() {
+        assert_eq!(
+            claim(None, true, |_| false),
+            (Some(ProfileKey::host()), None)
+        );
+        assert_eq!(
+            claim(Some("secret"), true, |_| true),
+            (Some(ProfileKey::host()), None)
+        );
+    }
+
+    #[test]
+    fn keys_do_not_hold_the_token() {
+        let key = ProfileKey::of("secret");
+        assert_eq!(key.0.len(), 64);
+        assert!(!key.0.contains("secret"));
+    }
+}
@@ -0,0 +1,111 @@
+use super::combat::CombatSettings;
+use super::health::Damage;
+use super::matches::Frozen;
+use super::replication::ReplicationClass;
+use super::validation::Throttled;
+use crate::protocol::{components::*, map::MapSettings, projectiles::*};
+use bevy::prelude::*;
+use lightyear::prelude::input::native::ActionState;
+use lightyear::prelude::server::Server;
+use lightyear::prelude::*;
+
+/// Ticks until the player may shoot again
+#[derive(Component, Default, Debug)]
+pub struct ShootCooldown(u16);
+
+pub fn track_new_player(trigger: On<Add, PlayerId>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity)
+        .insert(ShootCooldown::default());
+}
+
+/// Must run after the movement, the client spawns its predicted projectile from the moved
+/// position as well
+pub fn fire_projectiles(
+    mut players: Query<
+        (
+            &PlayerId,
+            &PlayerPosition,
+            &RoomId,
+            &LifeState,
+            &ActionState<Inputs>,
+            &mut ShootCooldown,
+        ),
+        (Without<Throttled>, Without<Frozen>),
+    >,
+    server: Single<&LocalTimeline, With<Server>>,
+    mut commands: Commands,
+) {
+    let tick = server.tick();
+    for (id, position, room, life, inputs, mut cooldown) in &mut players {
+        // same countdown as the client's `buffer_input`, so predicted shots line up
+        cooldown.0 = cooldown.0.saturating_sub(1);
+        if cooldown.0 > 0 {
+            continue;
+        }
+        let Inputs::Shoot { aim, .. } = inputs.0 else {
+            continue;
+        };
+        if !life.is_alive() {
+            continue;
+        }
+        cooldown.0 = SHOOT_COOLDOWN_TICKS;
+        commands.spawn((
+            projectile(id.0, position.0, aim, tick),
+            *room,
+            Replicate::to_clients(NetworkTarget::All),
+            ReplicationClass::Projectile,
+            NetworkVisibility::default(),
+            PredictionTarget::to_clients(NetworkTarget::Single(id.0)),
+            InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(id.0)),
+        ));
+    }
+}
+
+pub fn move_projectiles(
+    mut projectiles: Query<(
+        Entity,
+        &mut ObjectPosition,
+        &Projectile,
+        &mut ProjectileLifetime,
+    )>,
+    map: Res<MapSettings>,
+    mut commands: Commands,
+) {
+    for (entity, position, projectile, lifetime) in &mut projectiles {
+        if !shared_projectile_behaviour(position, projectile, lifetime, &map) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Damages the first living player of the room each projectile touches, except its owner
+pub fn projectile_hits(
+    projectiles: Query<(Entity, &Projectile, &ObjectPosition, &RoomId)>,
+    players: Query<(Entity, &PlayerId, &PlayerPosition, &RoomId, &LifeState)>,
+    settings: Res<CombatSettings>,
+    mut damage: MessageWriter<Damage>,
+    mut commands: Commands,
+) {
+    for (entity, projectile, position, room) in &projectiles {
+        let hit = players.iter().find(|(_, id, player, player_room, life)| {
+            id.0 != projectile.owner
+                && *player_room == room
+                && life.is_alive()
+                && player.distance(position.0) <= settings.projectile_hit_radius
+        });
+        let Some((target, ..)) = hit else {
+            continue;
+        };
+        let owner = players
+            .iter()
+            .find(|(_, id, ..)| id.0 == projectile.owner)
+            .map(|(owner, ..)| owner);
+        damage.write(Damage {
+            target,
+            amount: settings.projectile_damage,
+            source: owner,
+        });
+        commands.entity(entity).despawn();
+    }
+}
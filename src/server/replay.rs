@@ -0,0 +1,151 @@
+use super::matches::Frozen;
+use super::validation::Throttled;
+use crate::protocol::{components::*, map::MapSettings, replay::*, version::protocol_id};
+use bevy::prelude::*;
+use lightyear::prelude::LocalTimeline;
+use lightyear::prelude::input::native::ActionState;
+use lightyear::prelude::server::Server;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory the server writes its replay files into
+pub const REPLAY_DIRECTORY: &str = "./replays";
+
+/// Writes every simulated tick of the match into a replay file
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+    tick: u32,
+    /// Joins, leaves and teleports that happened since the last recorded tick
+    pending: Vec<ReplayRecord>,
+}
+
+impl ReplayRecorder {
+    fn create(path: &PathBuf, header: &ReplayHeader) -> io::Result<Self> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        header.write_to(&mut writer)?;
+        Ok(Self {
+            writer,
+            tick: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    fn write(&mut self, record: &ReplayRecord) -> io::Result<()> {
+        record.write_to(&mut self.writer)
+    }
+
+    /// Records that the server moved `player` outside of the movement. Must happen before the
+    /// movement of the tick.
+    pub fn teleport(&mut self, player: &PlayerId, position: Vec2) {
+        self.pending.push(ReplayRecord::Teleport {
+            player: player.0.to_bits(),
+            position,
+        });
+    }
+}
+
+pub fn start_recording(mut commands: Commands, map: Res<MapSettings>, time: Res<Time<Fixed>>) {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    let path = PathBuf::from(REPLAY_DIRECTORY).join(format!("{}.replay", since_epoch.as_secs()));
+
+    let header = ReplayHeader {
+        protocol_version: protocol_id(),
+        tick_duration: time.timestep(),
+        map: map.name.clone(),
+    };
+    match ReplayRecorder::create(&path, &header) {
+        Ok(recorder) => {
+            info!("Recording replay to {}", path.display());
+            commands.insert_resource(recorder);
+        }
+        Err(e) => error!("Could not create replay file {}: {e}", path.display()),
+    }
+}
+
+pub fn record_player_spawn(
+    trigger: On<Add, PlayerId>,
+    players: Query<(&PlayerId, &PlayerPosition)>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let (Some(mut recorder), Ok((id, position))) = (recorder, players.get(trigger.entity)) else {
+        return;
+    };
+    recorder.pending.push(ReplayRecord::Connect {
+        player: id.0.to_bits(),
+        position: position.0,
+    });
+}
+
+pub fn record_player_despawn(
+    trigger: On<Remove, PlayerId>,
+    players: Query<&PlayerId>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let (Some(mut recorder), Ok(id)) = (recorder, players.get(trigger.entity)) else {
+        return;
+    };
+    recorder.pending.push(ReplayRecord::Disconnect {
+        player: id.0.to_bits(),
+    });
+}
+
+/// Must run after the movement so that the recorded positions are the result of the recorded inputs
+pub fn record_tick(
+    mut commands: Commands,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    players: Query<(
+        &PlayerId,
+        &PlayerPosition,
+        Option<&ActionState<Inputs>>,
+        &LifeState,
+        Option<&SpeedBoost>,
+        Has<Throttled>,
+        Has<Frozen>,
+    )>,
+    server: Single<&LocalTimeline, With<Server>>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+
+    let tick = recorder.tick;
+    recorder.tick += 1;
+    let mut records = vec![ReplayRecord::Tick(tick)];
+    records.append(&mut recorder.pending);
+    let server_tick = server.tick().0;
+    for (id, _, inputs, life, boost, throttled, frozen) in &players {
+        // the inputs of throttled, frozen and dead players were ignored by the movement
+        if let (Some(inputs), true, false) = (inputs, life.is_alive(), throttled || frozen) {
+            records.push(ReplayRecord::Input {
+                player: id.0.to_bits(),
+                inputs: inputs.0.clone(),
+                boosted: boost.is_some_and(|boost| boost.is_active(server_tick)),
+            });
+        }
+    }
+    for (id, position, ..) in &players {
+        records.push(ReplayRecord::Position {
+            player: id.0.to_bits(),
+            position: position.0,
+        });
+    }
+
+    let result = records
+        .iter()
+        .try_for_each(|record| recorder.write(record))
+        .and_then(|_| recorder.writer.flush());
+    if let Err(e) = result {
+        error!("Stopped recording the replay: {e}");
+        commands.remove_resource::<ReplayRecorder>();
+    }
+}
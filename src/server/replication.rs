@@ -0,0 +1,335 @@
+use super::interest::{self, InterestSet, InterestSettings};
+use crate::protocol::components::*;
+use bevy::{ecs::entity::EntityHashMap, prelude::*};
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use std::time::Duration;
+
+/// Kind of replicated entity, deciding how often and how urgently it is sent
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReplicationClass {
+    Player,
+    Npc,
+    Projectile,
+    /// Pickups, matches and inventories only change now and then
+    Static,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClassSettings {
+    /// Share of the bandwidth budget. Entities that were held back gain priority until sent.
+    pub priority: f32,
+    /// Shortest time between two updates, `None` sends every change right away
+    pub send_interval: Option<Duration>,
+}
+
+/// How the class priorities and send rates are scaled for each client, by what matters to
+/// that client
+#[derive(Clone, Copy, Debug)]
+pub struct ViewerSettings {
+    /// Factor for the players of the client's own team, whatever the distance
+    pub team_factor: f32,
+    /// Distance up to which entities keep the full priority and rate of their class
+    pub near: f32,
+    /// Factor at the interest radius, it falls off linearly from `near` to there
+    pub far_factor: f32,
+}
+
+impl ViewerSettings {
+    /// Share of its class priority and rate an entity gets for a client. `distance` is `None`
+    /// for entities without a position or clients without a player.
+    pub fn factor(&self, same_team: bool, distance: Option<f32>, radius: Option<f32>) -> f32 {
+        if same_team {
+            return self.team_factor;
+        }
+        let (Some(distance), Some(radius)) = (distance, radius) else {
+            return 1.0;
+        };
+        let falloff = ((distance - self.near) / (radius - self.near).max(1.0)).clamp(0.0, 1.0);
+        1.0 + (self.far_factor - 1.0) * falloff
+    }
+
+    /// Priority of an entity of class priority `base` for a client
+    pub fn priority(
+        &self,
+        base: f32,
+        same_team: bool,
+        distance: Option<f32>,
+        radius: Option<f32>,
+    ) -> f32 {
+        base * self.factor(same_team, distance, radius)
+    }
+
+    /// Shortest time between two updates of an entity of class `interval` for a client,
+    /// `None` for classes sent on every change
+    pub fn send_interval(
+        &self,
+        interval: Option<Duration>,
+        same_team: bool,
+        distance: Option<f32>,
+        radius: Option<f32>,
+    ) -> Option<Duration> {
+        interval.map(|interval| interval.div_f32(self.factor(same_team, distance, radius)))
+    }
+}
+
+/// When each entity was last let through to one client, so that far away entities are sent
+/// at a fraction of their class rate
+#[derive(Component, Default, Debug)]
+pub struct ViewerSchedule(EntityHashMap<Duration>);
+
+impl ViewerSchedule {
+    /// Whether `entity` may be sent at `now`, at most once per `interval`. Counts as sent if so.
+    pub fn due(&mut self, entity: Entity, now: Duration, interval: Option<Duration>) -> bool {
+        let Some(interval) = interval else {
+            return true;
+        };
+        if self
+            .0
+            .get(&entity)
+            .is_some_and(|last| now < *last + interval)
+        {
+            return false;
+        }
+        self.0.insert(entity, now);
+        true
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ReplicationSettings {
+    /// How often the server looks for changes to send to a client, the fastest class rate
+    pub link_interval: Duration,
+    /// Bytes per second a client may receive. Over budget, the highest priorities go first.
+    pub bandwidth_budget: usize,
+    pub player: ClassSettings,
+    pub npc: ClassSettings,
+    pub projectile: ClassSettings,
+    pub static_props: ClassSettings,
+    pub viewer: ViewerSettings,
+}
+
+impl Default for ReplicationSettings {
+    fn default() -> Self {
+        Self {
+            link_interval: Duration::from_secs_f64(1.0 / 30.0),
+            bandwidth_budget: 16 * 1024,
+            player: ClassSettings {
+                priority: 10.0,
+                send_interval: Some(Duration::from_secs_f64(1.0 / 30.0)),
+            },
+            npc: ClassSettings {
+                priority: 4.0,
+                send_interval: Some(Duration::from_millis(100)),
+            },
+            // the owner predicts its projectiles, the others only need to see them fly
+            projectile: ClassSettings {
+                priority: 6.0,
+                send_interval: Some(Duration::from_millis(50)),
+            },
+            static_props: ClassSettings {
+                priority: 1.0,
+                send_interval: None,
+            },
+            // teammates at the full 30 Hz, players at the edge of the interest radius at a
+            // sixth of it: 5 Hz
+            viewer: ViewerSettings {
+                team_factor: 1.0,
+                near: 96.0,
+                far_factor: 1.0 / 6.0,
+            },
+        }
+    }
+}
+
+impl ReplicationSettings {
+    pub fn class(&self, class: ReplicationClass) -> ClassSettings {
+        match class {
+            ReplicationClass::Player => self.player,
+            ReplicationClass::Npc => self.npc,
+            ReplicationClass::Projectile => self.projectile,
+            ReplicationClass::Static => self.static_props,
+        }
+    }
+}
+
+/// Every client link sends at the rate of the fastest class, within its bandwidth budget
+pub fn link_sender(settings: &ReplicationSettings) -> impl Bundle {
+    (
+        ReplicationSender::new(
+            settings.link_interval,
+            SendUpdatesMode::SinceLastAck,
+            // hold back the low priority updates once the budget is used up
+            true,
+        ),
+        PriorityManager::new(PriorityConfig {
+            bandwidth_cap_enabled: true,
+            bandwidth_quota: settings.bandwidth_budget,
+        }),
+        ViewerSchedule::default(),
+    )
+}
+
+/// Puts every classified entity in its own replication group with the priority and send rate
+/// of its class. [`prioritize_per_viewer`] then adjusts the priority for each client.
+pub fn apply_class(
+    trigger: On<Add, ReplicationClass>,
+    classes: Query<&ReplicationClass>,
+    settings: Res<ReplicationSettings>,
+    mut commands: Commands,
+) {
+    let Ok(class) = classes.get(trigger.entity) else {
+        return;
+    };
+    let class = settings.class(*class);
+    let mut group = ReplicationGroup::new_from_entity().set_priority(class.priority);
+    if let Some(interval) = class.send_interval {
+        group = group.set_send_frequency(interval);
+    }
+    commands.entity(trigger.entity).insert(group);
+}
+
+/// Lightyear keeps the priority of each replication group per link. [`apply_class`] groups
+/// every entity on its own, so this overrides the priority of `entity` for one client only.
+fn set_link_priority(sender: &mut ReplicationSender, entity: Entity, priority: f32) {
+    sender.update_base_priority(ReplicationGroupId(entity.to_bits()), priority);
+}
+
+/// Must run after [`interest::update_visibility`]. Scales the class priority and rate of
+/// everything a client sees by its [`ViewerSettings`], with the same distances the interest
+/// radius uses.
+///
+/// The priority only orders the updates once the bandwidth budget is used up. The rate is
+/// kept with the [`ViewerSchedule`] of the link: an entity that is not due yet gets a priority
+/// of zero, which holds its update back for that client.
+pub fn prioritize_per_viewer(
+    settings: Res<ReplicationSettings>,
+    interest: Res<InterestSettings>,
+    time: Res<Time>,
+    mut links: Query<
+        (
+            Entity,
+            &InterestSet,
+            &mut ReplicationSender,
+            &mut ViewerSchedule,
+        ),
+        With<ClientOf>,
+    >,
+    viewers: Query<(&ControlledBy, &PlayerPosition, &Team), With<PlayerId>>,
+    entities: Query<(
+        &ReplicationClass,
+        Option<&Team>,
+        Option<&PlayerPosition>,
+        Option<&ObjectPosition>,
+    )>,
+) {
+    let now = time.elapsed();
+    for (link, visible, mut sender, mut schedule) in &mut links {
+        schedule.0.retain(|entity, _| visible.contains(*entity));
+        let viewer = viewers
+            .iter()
+            .find(|(controlled_by, ..)| controlled_by.owner == link)
+            .map(|(_, position, team)| (position.0, *team));
+
+        for entity in visible.iter() {
+            let Ok((class, team, player_position, object_position)) = entities.get(entity) else {
+                continue;
+            };
+            let same_team = viewer
+                .zip(team)
+                .is_some_and(|((_, viewer_team), team)| viewer_team == *team);
+            let distance = viewer
+                .zip(interest::position_of(player_position, object_position))
+                .map(|((viewer, _), position)| viewer.distance(position));
+            let class = settings.class(*class);
+            let interval = settings.viewer.send_interval(
+                class.send_interval,
+                same_team,
+                distance,
+                interest.radius,
+            );
+            let priority = if schedule.due(entity, now, interval) {
+                settings
+                    .viewer
+                    .priority(class.priority, same_team, distance, interest.radius)
+            } else {
+                0.0
+            };
+            set_link_priority(&mut sender, entity, priority);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: Option<f32> = Some(320.0);
+
+    fn viewer() -> ViewerSettings {
+        ReplicationSettings::default().viewer
+    }
+
+    #[test]
+    fn teammates_keep_their_priority_at_any_distance() {
+        assert_eq!(viewer().priority(10.0, true, Some(300.0), RADIUS), 10.0);
+        assert_eq!(viewer().priority(10.0, true, None, RADIUS), 10.0);
+    }
+
+    #[test]
+    fn priority_falls_off_from_near_to_the_radius() {
+        let viewer = viewer();
+        assert_eq!(viewer.priority(12.0, false, Some(0.0), RADIUS), 12.0);
+        assert_eq!(
+            viewer.priority(12.0, false, Some(viewer.near), RADIUS),
+            12.0
+        );
+        let halfway = (viewer.near + 320.0) / 2.0;
+        assert!((viewer.priority(12.0, false, Some(halfway), RADIUS) - 7.0).abs() < 1e-4);
+        assert!((viewer.priority(12.0, false, Some(320.0), RADIUS) - 2.0).abs() < 1e-4);
+        // beyond the radius, while the hysteresis keeps it visible
+        assert!((viewer.priority(12.0, false, Some(360.0), RADIUS) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn without_a_distance_the_class_priority_holds() {
+        assert_eq!(viewer().priority(4.0, false, None, RADIUS), 4.0);
+        assert_eq!(viewer().priority(4.0, false, Some(300.0), None), 4.0);
+    }
+
+    #[test]
+    fn far_players_are_sent_at_5_hz() {
+        let settings = ReplicationSettings::default();
+        let near = settings
+            .viewer
+            .send_interval(settings.player.send_interval, false, Some(10.0), RADIUS)
+            .unwrap();
+        let far = settings
+            .viewer
+            .send_interval(settings.player.send_interval, false, Some(320.0), RADIUS)
+            .unwrap();
+        assert!((near.as_secs_f32() - 1.0 / 30.0).abs() < 1e-4);
+        assert!((far.as_secs_f32() - 1.0 / 5.0).abs() < 1e-4);
+        assert_eq!(
+            settings
+                .viewer
+                .send_interval(None, false, Some(320.0), RADIUS),
+            None
+        );
+    }
+
+    #[test]
+    fn schedule_holds_updates_back_until_due() {
+        let entity = World::new().spawn_empty().id();
+        let interval = Some(Duration::from_millis(200));
+        let mut schedule = ViewerSchedule::default();
+        let ms = Duration::from_millis;
+        assert!(schedule.due(entity, ms(0), interval));
+        assert!(!schedule.due(entity, ms(100), interval));
+        assert!(!schedule.due(entity, ms(199), interval));
+        assert!(schedule.due(entity, ms(200), interval));
+        assert!(!schedule.due(entity, ms(250), interval));
+        // on change classes are never held back
+        assert!(schedule.due(entity, ms(250), None));
+    }
+}
@@ -0,0 +1,270 @@
+use super::console::ConsoleCommand;
+use crate::protocol::{components::*, messages::*};
+use bevy::prelude::*;
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use std::collections::BTreeMap;
+
+/// Room every client joins when it connects. It can't be closed.
+pub const LOBBY: RoomId = RoomId(0);
+/// Rooms a client may have open at once
+pub const MAX_ROOMS_PER_CREATOR: usize = 3;
+/// Longest room name a client may pick, in characters. Longer names are cut.
+pub const MAX_ROOM_NAME_CHARS: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct Room {
+    pub name: String,
+    /// Link of the client that created the room, `None` for rooms opened by the server
+    pub creator: Option<Entity>,
+}
+
+/// The independent matches hosted by this server
+#[derive(Resource, Debug)]
+pub struct Rooms {
+    rooms: BTreeMap<u32, Room>,
+    next_id: u32,
+}
+
+impl Default for Rooms {
+    fn default() -> Self {
+        let lobby = Room {
+            name: "lobby".to_string(),
+            creator: None,
+        };
+        Self {
+            rooms: BTreeMap::from([(LOBBY.0, lobby)]),
+            next_id: LOBBY.0 + 1,
+        }
+    }
+}
+
+impl Rooms {
+    pub fn create(&mut self, name: String, creator: Option<Entity>) -> RoomId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.rooms.insert(id, Room { name, creator });
+        RoomId(id)
+    }
+
+    /// Puts back a room under its old id, like when restoring a snapshot
+    pub fn restore(&mut self, id: RoomId, room: Room) {
+        self.next_id = self.next_id.max(id.0 + 1);
+        self.rooms.insert(id.0, room);
+    }
+
+    /// Open rooms created by the client of `link`
+    pub fn created_by(&self, link: Entity) -> usize {
+        self.rooms
+            .values()
+            .filter(|room| room.creator == Some(link))
+            .count()
+    }
+
+    pub fn get(&self, room: RoomId) -> Option<&Room> {
+        self.rooms.get(&room.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RoomId, &Room)> {
+        self.rooms.iter().map(|(id, room)| (RoomId(*id), room))
+    }
+
+    fn remove(&mut self, room: RoomId) -> bool {
+        room != LOBBY && self.rooms.remove(&room.0).is_some()
+    }
+}
+
+/// Closes `room`: its clients and their players go back to the lobby, every other entity
+/// of the room is despawned
+fn close_room(
+    room: RoomId,
+    rooms: &mut Rooms,
+    members: &mut Query<(Entity, &mut RoomId, Has<PlayerId>, Has<ClientOf>)>,
+    commands: &mut Commands,
+) -> bool {
+    if !rooms.remove(room) {
+        return false;
+    }
+    for (entity, mut member_room, is_player, is_client) in members {
+        if *member_room != room {
+            continue;
+        }
+        if is_player || is_client {
+            *member_room = LOBBY;
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+    true
+}
+
+/// Moves a client link and the player it controls into `room`
+fn join_room(
+    link: Entity,
+    room: RoomId,
+    members: &mut Query<(Entity, &mut RoomId, Has<PlayerId>, Has<ClientOf>)>,
+    players: &Query<(Entity, &ControlledBy), With<PlayerId>>,
+) {
+    let player = players
+        .iter()
+        .find(|(_, controlled_by)| controlled_by.owner == link)
+        .map(|(player, _)| player);
+    for entity in [Some(link), player].into_iter().flatten() {
+        if let Ok((_, mut member_room, ..)) = members.get_mut(entity) {
+            *member_room = room;
+        }
+    }
+}
+
+/// Only links that passed the handshake have a [`RoomId`], the others can't touch the rooms yet
+/// Name of the room `link` asks to create, cut to [`MAX_ROOM_NAME_CHARS`], unless it already
+/// has [`MAX_ROOMS_PER_CREATOR`] rooms open or the name is blank
+fn room_name(rooms: &Rooms, link: Entity, name: &str) -> Result<String, String> {
+    if rooms.created_by(link) >= MAX_ROOMS_PER_CREATOR {
+        return Err(format!("it already has {MAX_ROOMS_PER_CREATOR} rooms open"));
+    }
+    let name: String = name.trim().chars().take(MAX_ROOM_NAME_CHARS).collect();
+    if name.is_empty() {
+        return Err("the name is blank".to_string());
+    }
+    Ok(name)
+}
+
+pub fn handle_room_commands(
+    mut links: Query<(Entity, &mut MessageReceiver<RoomCommand>), (With<ClientOf>, With<RoomId>)>,
+    mut rooms: ResMut<Rooms>,
+    mut members: Query<(Entity, &mut RoomId, Has<PlayerId>, Has<ClientOf>)>,
+    players: Query<(Entity, &ControlledBy), With<PlayerId>>,
+    mut commands: Commands,
+) {
+    for (link, mut receiver) in &mut links {
+        for command in receiver.receive() {
+            match command {
+                // answered by `broadcast_room_list`
+                RoomCommand::List => rooms.set_changed(),
+                RoomCommand::Create { name } => match room_name(&rooms, link, &name) {
+                    Ok(name) => {
+                        let room = rooms.create(name, Some(link));
+                        info!("Client {link:?} created room {room:?}");
+                    }
+                    Err(reason) => warn!("Client {link:?} may not create a room: {reason}"),
+                },
+                RoomCommand::Join { room } => {
+                    let room = RoomId(room);
+                    if rooms.get(room).is_some() {
+                        join_room(link, room, &mut members, &players);
+                    } else {
+                        warn!("Client {link:?} tried to join unknown room {room:?}");
+                    }
+                }
+                RoomCommand::Close { room } => {
+                    let room = RoomId(room);
+                    if rooms.get(room).and_then(|room| room.creator) == Some(link) {
+                        close_room(room, &mut rooms, &mut members, &mut commands);
+                    } else {
+                        warn!("Client {link:?} may not close room {room:?}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn handle_console_commands(
+    mut console: MessageReader<ConsoleCommand>,
+    mut rooms: ResMut<Rooms>,
+    mut members: Query<(Entity, &mut RoomId, Has<PlayerId>, Has<ClientOf>)>,
+    mut commands: Commands,
+) {
+    for command in console.read() {
+        match command {
+            ConsoleCommand::ListRooms => {
+                for (id, room) in rooms.iter() {
+                    let players = members
+                        .iter()
+                        .filter(|(_, member_room, _, is_client)| **member_room == id && *is_client)
+                        .count();
+                    println!("{:>4}  {:<24} {players} players", id.0, room.name);
+                }
+            }
+            ConsoleCommand::CreateRoom { name } => {
+                let room = rooms.create(name.clone(), None);
+                println!("created room {}", room.0);
+            }
+            ConsoleCommand::CloseRoom { room } => {
+                if close_room(RoomId(*room), &mut rooms, &mut members, &mut commands) {
+                    println!("closed room {room}");
+                } else {
+                    println!("no room {room} to close");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends every client the up to date room list whenever rooms or memberships change
+pub fn broadcast_room_list(
+    rooms: Res<Rooms>,
+    changed: Query<(), Changed<RoomId>>,
+    mut links: Query<(&RoomId, &mut MessageSender<RoomList>), With<ClientOf>>,
+) {
+    if !rooms.is_changed() && changed.is_empty() {
+        return;
+    }
+
+    let infos: Vec<_> = rooms
+        .iter()
+        .map(|(id, room)| RoomInfo {
+            id: id.0,
+            name: room.name.clone(),
+            players: links.iter().filter(|(room, _)| **room == id).count(),
+        })
+        .collect();
+    for (room, mut sender) in &mut links {
+        sender.send::<LobbyChannel>(RoomList {
+            rooms: infos.clone(),
+            current: room.0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_trimmed_and_cut() {
+        let link = World::new().spawn_empty().id();
+        let rooms = Rooms::default();
+        assert_eq!(room_name(&rooms, link, "  arena "), Ok("arena".to_string()));
+        let long = "é".repeat(MAX_ROOM_NAME_CHARS + 10);
+        assert_eq!(
+            room_name(&rooms, link, &long).unwrap().chars().count(),
+            MAX_ROOM_NAME_CHARS
+        );
+        assert!(room_name(&rooms, link, "   ").is_err());
+    }
+
+    #[test]
+    fn creators_have_a_room_limit() {
+        let mut world = World::new();
+        let link = world.spawn_empty().id();
+        let other = world.spawn_empty().id();
+        let mut rooms = Rooms::default();
+        for index in 0..MAX_ROOMS_PER_CREATOR {
+            assert!(room_name(&rooms, link, "room").is_ok());
+            rooms.create(format!("room {index}"), Some(link));
+        }
+        assert!(room_name(&rooms, link, "room").is_err());
+        assert!(room_name(&rooms, other, "room").is_ok());
+
+        // closing one makes room for another
+        let (first, _) = rooms
+            .iter()
+            .find(|(_, room)| room.creator == Some(link))
+            .unwrap();
+        assert!(rooms.remove(first));
+        assert!(room_name(&rooms, link, "room").is_ok());
+    }
+}
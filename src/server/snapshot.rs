@@ -0,0 +1,423 @@
+use super::console::ConsoleCommand;
+use super::matches::{self, MatchTimer};
+use super::npcs::{self, NpcCatalog};
+use super::pickups::{self, FromSpawner};
+use super::profiles::ProfileKey;
+use super::rooms::{LOBBY, Room, Rooms};
+use crate::protocol::{
+    components::*, map::MapSettings, matches::MatchStatus, pickups::*, stats::PlayerStats,
+};
+use bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*};
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Bumped whenever [`WorldSnapshot`] changes shape. Snapshots of other versions are ignored.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Resource, Clone, Debug)]
+pub struct SnapshotSettings {
+    pub path: PathBuf,
+    /// Whether the server picks up the snapshot it finds on startup
+    pub restore: bool,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("./data/world.json"),
+            restore: true,
+        }
+    }
+}
+
+/// The gameplay state of every room, enough to carry the world over a server restart
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorldSnapshot {
+    pub version: u32,
+    pub rooms: Vec<RoomSnapshot>,
+    pub matches: Vec<(RoomId, MatchStatus)>,
+    pub players: Vec<PlayerSnapshot>,
+    pub npcs: Vec<NpcSnapshot>,
+    pub pickups: Vec<PickupSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoomSnapshot {
+    pub id: RoomId,
+    pub name: String,
+}
+
+/// Players can only come back once their client reconnects with the same profile, see
+/// [`RestoredPlayers`]. Guests have no profile and are not kept.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayerSnapshot {
+    pub profile: ProfileKey,
+    pub room: RoomId,
+    pub team: Team,
+    pub position: PlayerPosition,
+    pub health: Health,
+    pub stats: PlayerStats,
+    pub inventory: Inventory,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NpcSnapshot {
+    pub npc: Npc,
+    pub room: RoomId,
+    pub position: PlayerPosition,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PickupSnapshot {
+    pub pickup: Pickup,
+    /// Index of the map's spawner the pickup lies on
+    pub spawner: usize,
+    pub room: RoomId,
+}
+
+impl WorldSnapshot {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let snapshot: Self = serde_json::from_str(&json)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot version {} is not {SNAPSHOT_VERSION}",
+                    snapshot.version
+                ),
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes next to `path` first, so that a crash never leaves half a snapshot behind
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_string_pretty(self)?)?;
+        fs::rename(partial, path)
+    }
+}
+
+/// Players of the restored snapshot that did not reconnect yet
+#[derive(Resource, Default, Debug)]
+pub struct RestoredPlayers(pub HashMap<ProfileKey, PlayerSnapshot>);
+
+/// Everything [`WorldSnapshot`] is taken from
+#[derive(SystemParam)]
+pub struct SnapshotSource<'w, 's> {
+    rooms: Res<'w, Rooms>,
+    matches: Query<'w, 's, (&'static RoomId, &'static MatchStatus)>,
+    players: Query<
+        'w,
+        's,
+        (
+            &'static ProfileKey,
+            &'static RoomId,
+            &'static Team,
+            &'static PlayerPosition,
+            &'static Health,
+            &'static PlayerStats,
+            &'static ControlledBy,
+        ),
+    >,
+    inventories: Query<'w, 's, (&'static ControlledBy, &'static Inventory)>,
+    npcs: Query<'w, 's, (&'static Npc, &'static RoomId, &'static PlayerPosition)>,
+    pickups: Query<'w, 's, (&'static Pickup, &'static FromSpawner, &'static RoomId)>,
+    restored: Res<'w, RestoredPlayers>,
+}
+
+impl SnapshotSource<'_, '_> {
+    fn snapshot(&self) -> WorldSnapshot {
+        let players = self
+            .players
+            .iter()
+            .map(
+                |(key, room, team, position, health, stats, controlled_by)| PlayerSnapshot {
+                    profile: key.clone(),
+                    room: *room,
+                    team: *team,
+                    position: position.clone(),
+                    health: *health,
+                    stats: *stats,
+                    inventory: self
+                        .inventories
+                        .iter()
+                        .find(|(owner, _)| owner.owner == controlled_by.owner)
+                        .map(|(_, inventory)| inventory.clone())
+                        .unwrap_or_default(),
+                },
+            )
+            // players that did not come back since the last restart are kept for later
+            .chain(self.restored.0.values().cloned())
+            .collect();
+
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            rooms: self
+                .rooms
+                .iter()
+                .filter(|(id, _)| *id != LOBBY)
+                .map(|(id, room)| RoomSnapshot {
+                    id,
+                    name: room.name.clone(),
+                })
+                .collect(),
+            matches: self
+                .matches
+                .iter()
+                .map(|(room, status)| (*room, *status))
+                .collect(),
+            players,
+            npcs: self
+                .npcs
+                .iter()
+                .map(|(npc, room, position)| NpcSnapshot {
+                    npc: npc.clone(),
+                    room: *room,
+                    position: position.clone(),
+                })
+                .collect(),
+            pickups: self
+                .pickups
+                .iter()
+                .map(|(pickup, from, room)| PickupSnapshot {
+                    pickup: *pickup,
+                    spawner: from.0,
+                    room: *room,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Must run after [`npcs::load_npcs`]. Whatever the snapshot lacks is filled in by the usual
+/// spawn systems.
+pub fn restore_snapshot(
+    settings: Res<SnapshotSettings>,
+    mut rooms: ResMut<Rooms>,
+    mut restored: ResMut<RestoredPlayers>,
+    catalog: Res<NpcCatalog>,
+    map: Res<MapSettings>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    if !settings.restore || !settings.path.exists() {
+        return;
+    }
+    let snapshot = match WorldSnapshot::read(&settings.path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            error!(
+                "Could not restore the snapshot {}, starting fresh: {e}",
+                settings.path.display()
+            );
+            return;
+        }
+    };
+
+    for room in &snapshot.rooms {
+        rooms.restore(
+            room.id,
+            Room {
+                name: room.name.clone(),
+                creator: None,
+            },
+        );
+    }
+    for (room, status) in &snapshot.matches {
+        let mut entity = commands.spawn(matches::match_entity(*room, *status));
+        if status.remaining_secs > 0 {
+            entity.insert(MatchTimer {
+                ends_at: time.elapsed() + Duration::from_secs(status.remaining_secs as u64),
+            });
+        }
+    }
+    for npc in &snapshot.npcs {
+        match catalog
+            .npcs
+            .iter()
+            .position(|definition| definition.id == npc.npc.kind)
+        {
+            Some(index) => {
+                commands.spawn(npcs::npc(&catalog, index, npc.room, npc.position.0));
+            }
+            None => warn!(
+                "Dropping NPC {:?} that is no longer in the catalog",
+                npc.npc.kind
+            ),
+        }
+    }
+    for pickup in &snapshot.pickups {
+        if pickup.spawner < map.pickups.len() {
+            commands.spawn(pickups::pickup(
+                pickup.pickup.0,
+                &map,
+                pickup.spawner,
+                pickup.room,
+            ));
+        }
+    }
+    restored.0 = snapshot
+        .players
+        .iter()
+        .map(|player| (player.profile.clone(), player.clone()))
+        .collect();
+
+    info!(
+        "Restored {} rooms, {} NPCs, {} pickups and {} players from {}",
+        snapshot.rooms.len(),
+        snapshot.npcs.len(),
+        snapshot.pickups.len(),
+        snapshot.players.len(),
+        settings.path.display()
+    );
+}
+
+/// Writes the snapshot on the console's `snapshot` command and when the server shuts down
+pub fn save_snapshot(
+    mut console: MessageReader<ConsoleCommand>,
+    mut exits: MessageReader<AppExit>,
+    source: SnapshotSource,
+    settings: Res<SnapshotSettings>,
+) {
+    let requested = console
+        .read()
+        .any(|command| *command == ConsoleCommand::Snapshot);
+    let exiting = exits.read().count() > 0;
+    if !requested && !exiting {
+        return;
+    }
+
+    let snapshot = source.snapshot();
+    match snapshot.write(&settings.path) {
+        Ok(()) => info!(
+            "Saved the world with {} players to {}",
+            snapshot.players.len(),
+            settings.path.display()
+        ),
+        Err(e) => error!(
+            "Could not save the snapshot {}: {e}",
+            settings.path.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::matches::MatchState;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("snapshot-{name}-{}", std::process::id()))
+            .join("world.json")
+    }
+
+    fn player(profile: &str, room: RoomId) -> PlayerSnapshot {
+        PlayerSnapshot {
+            profile: ProfileKey(profile.to_string()),
+            room,
+            team: Team::Blue,
+            position: PlayerPosition(Vec2::new(12.0, -4.5)),
+            health: Health::full(MAX_HEALTH),
+            stats: PlayerStats::default(),
+            inventory: Inventory::default(),
+        }
+    }
+
+    fn snapshot() -> WorldSnapshot {
+        let room = RoomId(3);
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            rooms: vec![RoomSnapshot {
+                id: room,
+                name: "arena".to_string(),
+            }],
+            matches: vec![(
+                room,
+                MatchStatus {
+                    state: MatchState::InProgress,
+                    remaining_secs: 42,
+                },
+            )],
+            players: vec![player("alice", room), player("bob", LOBBY)],
+            npcs: Vec::new(),
+            pickups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let path = temp_path("round-trip");
+        let written = snapshot();
+        written.write(&path).unwrap();
+        assert!(!path.with_extension("partial").exists());
+        assert_eq!(WorldSnapshot::read(&path).unwrap(), written);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("version");
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION + 1,
+            ..snapshot()
+        }
+        .write(&path)
+        .unwrap();
+        let error = WorldSnapshot::read(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restoring_brings_back_rooms_and_players() {
+        let path = temp_path("restore");
+        WorldSnapshot {
+            matches: Vec::new(),
+            ..snapshot()
+        }
+        .write(&path)
+        .unwrap();
+
+        let mut world = World::new();
+        world.insert_resource(SnapshotSettings {
+            path: path.clone(),
+            restore: true,
+        });
+        world.init_resource::<Rooms>();
+        world.init_resource::<RestoredPlayers>();
+        world.init_resource::<NpcCatalog>();
+        world.init_resource::<MapSettings>();
+        world.init_resource::<Time>();
+        world.run_system_once(restore_snapshot).unwrap();
+
+        let rooms = world.resource::<Rooms>();
+        assert_eq!(rooms.get(RoomId(3)).unwrap().name, "arena");
+        assert!(rooms.get(LOBBY).is_some());
+        // new rooms don't take the id of a restored one
+        assert_eq!(
+            world
+                .resource_mut::<Rooms>()
+                .create("next".to_string(), None),
+            RoomId(4)
+        );
+        let restored = &world.resource::<RestoredPlayers>().0;
+        assert_eq!(restored.len(), 2);
+        assert_eq!(
+            restored[&ProfileKey("alice".to_string())],
+            player("alice", RoomId(3))
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use super::health::Died;
+use super::matches::MatchEnded;
+use super::pickups::Collected;
+use super::profiles::ProfileName;
+use super::rooms::Rooms;
+use crate::protocol::{components::*, messages::EventChannel, stats::*};
+use bevy::{platform::collections::HashSet, prelude::*};
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory the server writes the final standings of every round into
+pub const RESULTS_DIRECTORY: &str = "./results";
+
+#[derive(Serialize, Debug)]
+struct MatchResults {
+    room: String,
+    /// Seconds since the unix epoch
+    finished_at: u64,
+    standings: Vec<Standing>,
+}
+
+#[derive(Serialize, Debug)]
+struct Standing {
+    rank: usize,
+    player: u64,
+    name: String,
+    kills: u32,
+    deaths: u32,
+    pickups: u32,
+    score: u32,
+}
+
+pub fn count_kills(mut deaths: MessageReader<Died>, mut players: Query<&mut PlayerStats>) {
+    for death in deaths.read() {
+        if let Ok(mut victim) = players.get_mut(death.victim) {
+            victim.deaths += 1;
+        }
+        if let Some(Ok(mut killer)) = death.killer.map(|killer| players.get_mut(killer)) {
+            killer.kills += 1;
+            killer.score += KILL_SCORE;
+        }
+    }
+}
+
+pub fn count_pickups(
+    mut collected: MessageReader<Collected>,
+    mut players: Query<&mut PlayerStats>,
+) {
+    for collected in collected.read() {
+        if let Ok(mut stats) = players.get_mut(collected.player) {
+            stats.pickups += 1;
+            stats.score += PICKUP_SCORE;
+        }
+    }
+}
+
+/// Name the standings show for a player, every player but the ones spawned without a
+/// profile has one
+fn display_name(id: &PlayerId, name: Option<&ProfileName>) -> String {
+    name.map_or_else(
+        || format!("player {}", id.0.to_bits()),
+        |name| name.0.clone(),
+    )
+}
+
+/// Sends the [`Standings`] of every room where a score changed or someone came or went to all
+/// clients in it
+pub fn broadcast_standings(
+    changed: Query<&RoomId, Or<(Changed<PlayerStats>, Changed<RoomId>)>>,
+    mut left: RemovedComponents<PlayerId>,
+    players: Query<(&PlayerId, Option<&ProfileName>, &PlayerStats, &RoomId)>,
+    mut links: Query<(&RoomId, &mut MessageSender<Standings>), With<ClientOf>>,
+) {
+    let mut rooms: HashSet<RoomId> = changed.iter().copied().collect();
+    // the room of a player that is gone can't be told anymore
+    if left.read().count() > 0 {
+        rooms.extend(links.iter().map(|(room, _)| *room));
+    }
+
+    for room in rooms {
+        let mut entries: Vec<_> = players
+            .iter()
+            .filter(|(.., player_room)| **player_room == room)
+            .map(|(id, name, stats, _)| StandingsEntry {
+                player: id.0,
+                name: display_name(id, name),
+                stats: *stats,
+            })
+            .collect();
+        entries.sort_by(|a, b| compare_standings((a.player, &a.stats), (b.player, &b.stats)));
+        let standings = Standings { entries };
+        for (link_room, mut sender) in &mut links {
+            if *link_room == room {
+                sender.send::<EventChannel>(standings.clone());
+            }
+        }
+    }
+}
+
+/// Writes the sorted standings of every finished round into a JSON file
+pub fn write_results(
+    mut ended: MessageReader<MatchEnded>,
+    players: Query<(&PlayerId, Option<&ProfileName>, &PlayerStats, &RoomId)>,
+    rooms: Res<Rooms>,
+) {
+    for MatchEnded { room } in ended.read() {
+        let mut standings: Vec<_> = players
+            .iter()
+            .filter(|(.., player_room)| *player_room == room)
+            .map(|(id, name, stats, _)| (id, name, stats))
+            .collect();
+        standings.sort_by(|a, b| compare_standings((a.0.0, a.2), (b.0.0, b.2)));
+
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        let results = MatchResults {
+            room: rooms
+                .get(*room)
+                .map_or_else(String::new, |room| room.name.clone()),
+            finished_at,
+            standings: standings
+                .into_iter()
+                .enumerate()
+                .map(|(index, (id, name, stats))| Standing {
+                    rank: index + 1,
+                    player: id.0.to_bits(),
+                    name: display_name(id, name),
+                    kills: stats.kills,
+                    deaths: stats.deaths,
+                    pickups: stats.pickups,
+                    score: stats.score,
+                })
+                .collect(),
+        };
+
+        let path =
+            PathBuf::from(RESULTS_DIRECTORY).join(format!("{finished_at}-room{}.json", room.0));
+        match save(&path, &results) {
+            Ok(()) => info!("Wrote the results of room {} to {}", room.0, path.display()),
+            Err(e) => error!("Could not write results {}: {e}", path.display()),
+        }
+    }
+}
+
+fn save(path: &Path, results: &MatchResults) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, results).map_err(io::Error::other)
+}
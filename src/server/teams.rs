@@ -0,0 +1,101 @@
+use crate::protocol::{components::*, matches::*, messages::SelectTeam};
+use bevy::prelude::*;
+use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::*;
+
+/// How much damage players deal to their own team
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriendlyFire {
+    Off,
+    Full,
+    /// Percentage of the damage teammates take
+    Reduced(u32),
+}
+
+impl FriendlyFire {
+    pub fn scale(self, amount: u32) -> u32 {
+        match self {
+            FriendlyFire::Off => 0,
+            FriendlyFire::Full => amount,
+            FriendlyFire::Reduced(percent) => amount * percent / 100,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct TeamSettings {
+    pub friendly_fire: FriendlyFire,
+}
+
+impl Default for TeamSettings {
+    fn default() -> Self {
+        Self {
+            friendly_fire: FriendlyFire::Off,
+        }
+    }
+}
+
+/// The team of `room` with the fewest players, red on a tie
+pub fn balanced_team<'a>(
+    room: RoomId,
+    players: impl Iterator<Item = (&'a RoomId, &'a Team)>,
+) -> Team {
+    let mut counts = [0; Team::ALL.len()];
+    for (_, team) in players.filter(|(player_room, _)| **player_room == room) {
+        counts[*team as usize] += 1;
+    }
+    Team::ALL
+        .into_iter()
+        .min_by_key(|team| counts[*team as usize])
+        .unwrap_or(Team::Red)
+}
+
+/// Balances the teams of the room a player moved into
+pub fn balance_on_room_change(
+    moved: Query<(Entity, Ref<RoomId>), With<PlayerId>>,
+    mut players: Query<(Entity, &RoomId, &mut Team), With<PlayerId>>,
+) {
+    for (player, room) in &moved {
+        // `handle_connected` already picked the team of new players
+        if !room.is_changed() || room.is_added() {
+            continue;
+        }
+        let team = balanced_team(
+            *room,
+            players
+                .iter()
+                .filter(|(other, ..)| *other != player)
+                .map(|(_, room, team)| (room, team)),
+        );
+        if let Ok((_, _, mut current)) = players.get_mut(player) {
+            current.set_if_neq(team);
+        }
+    }
+}
+
+/// Applies team switches requested from the lobby, unless a round is being played in the
+/// player's room
+pub fn select_team(
+    mut links: Query<(Entity, &mut MessageReceiver<SelectTeam>), With<ClientOf>>,
+    mut players: Query<(&ControlledBy, &RoomId, &mut Team), With<PlayerId>>,
+    matches: Query<(&RoomId, &MatchStatus)>,
+) {
+    for (link, mut receiver) in &mut links {
+        for SelectTeam(team) in receiver.receive() {
+            let Some((_, room, mut current)) = players
+                .iter_mut()
+                .find(|(controlled_by, ..)| controlled_by.owner == link)
+            else {
+                continue;
+            };
+            let in_round = matches.iter().any(|(match_room, status)| {
+                match_room == room && status.state == MatchState::InProgress
+            });
+            if in_round {
+                warn!("Client {link:?} can't switch teams during a round");
+                continue;
+            }
+            current.set_if_neq(team);
+        }
+    }
+}
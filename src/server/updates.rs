@@ -1,56 +1,150 @@
 use super::*;
-use crate::protocol::components::*;
+use crate::protocol::{
+    characters::CharacterRegistry,
+    components::*,
+    map::MapSettings,
+    messages::{HandshakeChannel, ProfileIssued, SelectAppearance},
+    quantize,
+};
 use bevy::{ecs::error::info, prelude::*};
 use lightyear::prelude::input::native::ActionState;
-use lightyear::prelude::server::ClientOf;
+use lightyear::prelude::server::{ClientOf, Server};
 use lightyear::prelude::*;
-use std::time::Duration;
+use matches::Frozen;
+use profiles::{Profile, ProfileClaim, ProfileKey, ProfileName, ProfileStore};
+use replication::{ReplicationClass, ReplicationSettings};
+use snapshot::RestoredPlayers;
+use validation::Throttled;
 
 /// When a new client tries to connect to a server, an entity is created for it with the `LinkOf` component.
 /// This entity represents the link between the server and that client.
 ///
-/// You can add additional components to update the link. In this case we will add a `ReplicationSender` that
-/// will enable us to replicate local entities to that client.
+/// You can add additional components to update the link. The `ReplicationSender` is only added once the
+/// client passed the handshake, see [`handle_connected`].
 pub fn handle_new_client(trigger: On<Add, LinkOf>, mut commands: Commands) {
     info!("Handle new client");
-    commands.entity(trigger.entity).insert((
-        ReplicationSender::new(
-            Duration::from_millis(100),
-            SendUpdatesMode::SinceLastAck,
-            false,
-        ),
-        Name::from("Client"),
-    ));
+    commands.entity(trigger.entity).insert(Name::from("Client"));
 }
 
 /// If the new client connects to the server, we want to spawn a new player entity for it.
 ///
-/// We have to react specifically on `Connected` because there is no guarantee that the connection request we
-/// received was valid. The server could reject the connection attempt for many reasons (server is full, packet is invalid,
-/// DDoS attempt, etc.). We want to start the replication only when the client is confirmed as connected.
+/// We can't react on `Connected` because there is no guarantee that the client runs a compatible build.
+/// The server could reject it for many reasons (outdated version, other protocol, no hello, etc.), so we
+/// start the replication and spawn the player only when the handshake accepted the client.
 pub fn handle_connected(
-    trigger: On<Add, Connected>,
-    query: Query<&RemoteId, With<ClientOf>>,
+    trigger: On<handshake::Accepted>,
+    mut query: Query<
+        (
+            &RemoteId,
+            Option<&ProfileClaim>,
+            Has<HostClient>,
+            Option<&mut MessageSender<ProfileIssued>>,
+        ),
+        With<ClientOf>,
+    >,
+    players: Query<(&PlayerPosition, &RoomId, &Team), With<PlayerId>>,
+    keys: Query<&ProfileKey>,
+    registry: Res<CharacterRegistry>,
+    map: Res<MapSettings>,
+    store: Option<Res<ProfileStore>>,
+    restored: Res<RestoredPlayers>,
+    room_list: Res<rooms::Rooms>,
+    settings: Res<ReplicationSettings>,
     mut commands: Commands,
 ) {
-    let Ok(client_id) = query.get(trigger.entity) else {
+    let Ok((client_id, claim, host, sender)) = query.get_mut(trigger.entity) else {
         warn!("RemoteId not found");
         return;
     };
     let client_id = client_id.0;
-    let entity = commands
-        .spawn((
-            PlayerBundle::new(client_id, Vec2::ZERO),
-            // we replicate the Player entity to all clients that are connected to this server
-            Replicate::to_clients(NetworkTarget::All),
-            PredictionTarget::to_clients(NetworkTarget::Single(client_id)),
-            // InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(client_id)),
-            ControlledBy {
-                owner: trigger.entity,
-                lifetime: Default::default(),
-            },
-        ))
-        .id();
+    // the profile belongs to whoever holds the token the server issued for it, the client id
+    // is picked by the client itself and proves nothing
+    let token = claim.and_then(|claim| claim.0.as_deref());
+    let (key, issued) = profiles::claim(token, host, |key| keys.iter().any(|used| used == key));
+    if let (Some(token), Some(mut sender)) = (issued, sender) {
+        sender.send::<HandshakeChannel>(ProfileIssued { token });
+    }
+    commands.entity(trigger.entity).remove::<ProfileClaim>();
+    // players left in a restored snapshot go back to their room, team, position and round.
+    // `pickups::spawn_inventory` gives them their inventory and forgets them.
+    let saved = key
+        .as_ref()
+        .and_then(|key| restored.0.get(key))
+        .filter(|saved| room_list.get(saved.room).is_some());
+    // every other client starts in the lobby, `update_visibility` limits replication to the
+    // entities of its room that are close to its player
+    let room = saved.map_or(rooms::LOBBY, |saved| saved.room);
+    commands.entity(trigger.entity).insert((
+        replication::link_sender(&settings),
+        room,
+        interest::InterestSet::default(),
+    ));
+    // new players join the smaller team of their room
+    let team = saved.map(|saved| saved.team).unwrap_or_else(|| {
+        teams::balanced_team(room, players.iter().map(|(_, room, team)| (room, team)))
+    });
+    let room_players = players
+        .iter()
+        .filter(|(_, player_room, _)| **player_room == room)
+        .map(|(position, ..)| position.0);
+    // returning players get back their name, look and lifetime stats. They also continue where
+    // they left, unless the map changed and put a wall there.
+    let profile = store
+        .zip(key.as_ref())
+        .and_then(|(store, key)| {
+            store
+                .load(key)
+                .inspect_err(|e| error!("Could not load the profile of {client_id:?}: {e}"))
+                .ok()
+                .flatten()
+        })
+        .unwrap_or_else(|| Profile::new(client_id, registry.default_appearance()));
+    let appearance = if registry.is_valid(&profile.appearance) {
+        profile.appearance
+    } else {
+        registry.default_appearance()
+    };
+    let position = saved
+        .map(|saved| saved.position.0)
+        .or(profile.last_position)
+        .filter(|position| !map.blocks(*position))
+        .unwrap_or_else(|| map.spawn_point(Some(team), room_players));
+    // saved positions may predate the grid, the client's prediction starts from a snapped one
+    let position = quantize::snap(position);
+    let mut player = commands.spawn_empty();
+    // before the rest, so that `pickups::spawn_inventory` finds the restored inventory
+    if let Some(key) = key {
+        player.insert(key);
+    }
+    player.insert((
+        PlayerBundle::new(client_id, position),
+        team,
+        appearance,
+        ProfileName(profile.name),
+        profile.lifetime,
+        room,
+        // we replicate the Player entity to all clients that are connected to this server
+        Replicate::to_clients(NetworkTarget::All),
+        ReplicationClass::Player,
+        NetworkVisibility::default(),
+        PredictionTarget::to_clients(NetworkTarget::Single(client_id)),
+        InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(client_id)),
+        ControlledBy {
+            owner: trigger.entity,
+            lifetime: Default::default(),
+        },
+    ));
+    if let Some(saved) = saved {
+        // dead players come back alive
+        let health = if saved.health.current > 0 {
+            saved.health
+        } else {
+            Health::full(saved.health.max)
+        };
+        player.insert((health, saved.stats));
+    }
+
+    let entity = player.id();
 
     info!(
         "Create player entity {:?} for client {:?}",
@@ -59,8 +153,46 @@ pub fn handle_connected(
 }
 
 /// Read client inputs and move players in server therefore giving a basis for other clients
-pub fn movement(mut position_query: Query<(&mut PlayerPosition, &ActionState<Inputs>)>) {
-    for (position, inputs) in position_query.iter_mut() {
-        shared_movement_behaviour(position, inputs);
+pub fn movement(
+    mut position_query: Query<
+        (
+            &mut PlayerPosition,
+            &mut PlayerVelocity,
+            &ActionState<Inputs>,
+            &LifeState,
+            Option<&SpeedBoost>,
+        ),
+        (Without<Throttled>, Without<Frozen>),
+    >,
+    server: Single<&LocalTimeline, With<Server>>,
+) {
+    let tick = server.tick().0;
+    for (position, velocity, inputs, life, boost) in position_query.iter_mut() {
+        if life.is_alive() {
+            let boosted = boost.is_some_and(|boost| boost.is_active(tick));
+            shared_movement_behaviour(position, velocity, inputs, boosted);
+        }
+    }
+}
+
+/// Applies the appearance a client picked in the menu if the character registry knows it
+pub fn select_appearance(
+    mut links: Query<(Entity, &mut MessageReceiver<SelectAppearance>), With<ClientOf>>,
+    mut players: Query<(&ControlledBy, &mut PlayerAppearance)>,
+    registry: Res<CharacterRegistry>,
+) {
+    for (link, mut receiver) in &mut links {
+        for SelectAppearance(appearance) in receiver.receive() {
+            if !registry.is_valid(&appearance) {
+                warn!("Client {link:?} picked unknown appearance {appearance:?}");
+                continue;
+            }
+            if let Some((_, mut current)) = players
+                .iter_mut()
+                .find(|(controlled_by, _)| controlled_by.owner == link)
+            {
+                current.set_if_neq(appearance);
+            }
+        }
     }
 }
@@ -0,0 +1,607 @@
+//! Server side checks of the inputs clients send.
+//!
+//! The server simulates every position itself, so a client can't claim one. What it can do is
+//! send inputs no player produces, or inputs for more ticks than the server runs by speeding
+//! up its clock: the speed check compares the distance the inputs received between two
+//! confirmed ticks imply with what walking allows in that time.
+
+use crate::protocol::components::*;
+use bevy::prelude::*;
+use lightyear::prelude::input::{InputBuffer, native::ActionState};
+use lightyear::prelude::server::Server;
+use lightyear::prelude::*;
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Thresholds of the server side input checks and how the server escalates against clients
+/// that keep failing them.
+///
+/// Insert it before adding the `ServerPlugin` to override the defaults.
+#[derive(Resource, Clone, Debug)]
+pub struct ValidationPolicy {
+    /// Most input changes per second a human can produce
+    pub max_input_changes_per_second: u32,
+    /// How many ticks the newest input of a client may be ahead of the server tick
+    pub max_ticks_ahead: i32,
+    /// How many ticks the newest input of a client may lag behind the server tick
+    pub max_ticks_behind: i32,
+    /// Server ticks the distance implied by the inputs is summed over
+    pub speed_window_ticks: u16,
+    /// Share by which the implied distance may exceed walking diagonally the whole window
+    pub speed_tolerance: f32,
+    /// Flags after which the inputs of the client are ignored for `throttle_duration`
+    pub throttle_after: u32,
+    pub throttle_duration: Duration,
+    /// Flags after which the client is disconnected
+    pub kick_after: u32,
+    /// Flags are forgotten once a client went this long without a new one
+    pub forgive_after: Duration,
+    /// File every flag is appended to
+    pub audit_log: PathBuf,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            max_input_changes_per_second: 20,
+            max_ticks_ahead: 32,
+            max_ticks_behind: 16,
+            speed_window_ticks: 64,
+            speed_tolerance: 0.25,
+            throttle_after: 3,
+            throttle_duration: Duration::from_secs(2),
+            kick_after: 8,
+            forgive_after: Duration::from_secs(30),
+            audit_log: PathBuf::from("./logs/audit.log"),
+        }
+    }
+}
+
+/// Suspicious behaviour detected on a client
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    InputRate { changes: u32 },
+    OpposingDirections,
+    TicksAhead { ticks: i32 },
+    TicksBehind { ticks: i32 },
+    SpeedAnomaly { implied: f32, allowed: f32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::InputRate { changes } => write!(f, "{changes} input changes in one second"),
+            Violation::OpposingDirections => write!(f, "opposing directions held together"),
+            Violation::TicksAhead { ticks } => {
+                write!(f, "inputs {ticks} ticks ahead of the server")
+            }
+            Violation::TicksBehind { ticks } => write!(f, "inputs {ticks} ticks behind the server"),
+            Violation::SpeedAnomaly { implied, allowed } => write!(
+                f,
+                "inputs implying {implied:.1} px where walking allows {allowed:.1} px"
+            ),
+        }
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+pub struct InputFlagged {
+    /// The player entity of the offending client
+    pub player: Entity,
+    pub violation: Violation,
+}
+
+/// What the server does about a flagged client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Log,
+    Throttle,
+    Kick,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Log => write!(f, "log"),
+            Action::Throttle => write!(f, "throttle"),
+            Action::Kick => write!(f, "kick"),
+        }
+    }
+}
+
+/// Per player bookkeeping of the input checks
+#[derive(Component, Debug)]
+pub struct InputValidation {
+    last_inputs: Inputs,
+    changes: u32,
+    window_start: Duration,
+    strikes: u32,
+    last_strike: Duration,
+    /// Newest input tick already counted by the speed check
+    last_input_tick: Option<Tick>,
+    speed_window_start: Option<Tick>,
+    implied_distance: f32,
+}
+
+impl InputValidation {
+    pub fn new(now: Duration) -> Self {
+        Self {
+            last_inputs: Inputs::default(),
+            changes: 0,
+            window_start: now,
+            strikes: 0,
+            last_strike: Duration::ZERO,
+            last_input_tick: None,
+            speed_window_start: None,
+            implied_distance: 0.0,
+        }
+    }
+
+    /// Adds the distance implied by newly received inputs at the server's `tick`. Once
+    /// `speed_window_ticks` passed, compares the sum with what walking allows and starts over.
+    pub fn measure_speed(
+        &mut self,
+        tick: Tick,
+        implied: f32,
+        policy: &ValidationPolicy,
+    ) -> Option<Violation> {
+        let start = *self.speed_window_start.get_or_insert(tick);
+        self.implied_distance += implied;
+        let elapsed = tick - start;
+        if elapsed < policy.speed_window_ticks as i16 {
+            return None;
+        }
+
+        let implied = std::mem::take(&mut self.implied_distance);
+        self.speed_window_start = Some(tick);
+        let walked = Direction {
+            up: true,
+            right: true,
+            ..default()
+        }
+        .step(MOVE_SPEED)
+        .length();
+        let allowed = elapsed as f32 * walked * (1.0 + policy.speed_tolerance);
+        (implied > allowed).then_some(Violation::SpeedAnomaly { implied, allowed })
+    }
+
+    /// Counts a flag raised at `now`. Strikes are forgotten after a quiet `forgive_after`.
+    pub fn strike(&mut self, now: Duration, policy: &ValidationPolicy) -> Action {
+        if now - self.last_strike > policy.forgive_after {
+            self.strikes = 0;
+        }
+        self.strikes += 1;
+        self.last_strike = now;
+
+        if self.strikes >= policy.kick_after {
+            Action::Kick
+        } else if self.strikes >= policy.throttle_after {
+            Action::Throttle
+        } else {
+            Action::Log
+        }
+    }
+}
+
+/// The server ignores the inputs of this player until `until`
+#[derive(Component, Debug)]
+pub struct Throttled {
+    until: Duration,
+}
+
+/// Append only record of every flag raised by the input checks
+#[derive(Resource, Default)]
+pub struct AuditLog {
+    file: Option<File>,
+}
+
+pub fn open_audit_log(mut audit_log: ResMut<AuditLog>, policy: Res<ValidationPolicy>) {
+    if let Some(directory) = policy.audit_log.parent() {
+        let _ = fs::create_dir_all(directory);
+    }
+    match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&policy.audit_log)
+    {
+        Ok(file) => audit_log.file = Some(file),
+        Err(e) => error!(
+            "Could not open audit log {}: {e}",
+            policy.audit_log.display()
+        ),
+    }
+}
+
+pub fn track_new_player(trigger: On<Add, PlayerId>, time: Res<Time>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity)
+        .insert(InputValidation::new(time.elapsed()));
+}
+
+/// Flags inputs that change faster than a human can press keys or that no genuine client sends
+pub fn check_inputs(
+    mut players: Query<(Entity, &ActionState<Inputs>, &mut InputValidation)>,
+    policy: Res<ValidationPolicy>,
+    time: Res<Time>,
+    mut flags: MessageWriter<InputFlagged>,
+) {
+    let now = time.elapsed();
+    for (player, inputs, mut validation) in &mut players {
+        if now - validation.window_start >= Duration::from_secs(1) {
+            validation.window_start = now;
+            validation.changes = 0;
+        }
+        if inputs.0 != validation.last_inputs {
+            validation.last_inputs = inputs.0.clone();
+            validation.changes += 1;
+            if validation.changes == policy.max_input_changes_per_second + 1 {
+                flags.write(InputFlagged {
+                    player,
+                    violation: Violation::InputRate {
+                        changes: validation.changes,
+                    },
+                });
+            }
+
+            let direction = inputs.0.direction();
+            if (direction.up && direction.down) || (direction.left && direction.right) {
+                flags.write(InputFlagged {
+                    player,
+                    violation: Violation::OpposingDirections,
+                });
+            }
+        }
+    }
+}
+
+/// Flags clients whose inputs arrive too far ahead of or behind the server tick
+pub fn check_input_timing(
+    players: Query<(Entity, &InputBuffer<ActionState<Inputs>>), With<InputValidation>>,
+    server: Single<&LocalTimeline, With<Server>>,
+    policy: Res<ValidationPolicy>,
+    mut flags: MessageWriter<InputFlagged>,
+) {
+    let tick = server.tick();
+    for (player, buffer) in &players {
+        let Some(end_tick) = buffer.end_tick() else {
+            continue;
+        };
+        if let Some(violation) = timing_violation(end_tick, tick, &policy) {
+            flags.write(InputFlagged { player, violation });
+        }
+    }
+}
+
+/// Whether the newest input at `end_tick` is too far from the server's `tick`
+fn timing_violation(end_tick: Tick, tick: Tick, policy: &ValidationPolicy) -> Option<Violation> {
+    let ticks = (end_tick - tick) as i32;
+    if ticks > policy.max_ticks_ahead {
+        Some(Violation::TicksAhead { ticks })
+    } else if -ticks > policy.max_ticks_behind {
+        Some(Violation::TicksBehind { ticks: -ticks })
+    } else {
+        None
+    }
+}
+
+/// Sums the distance the newly received inputs of every client imply and flags the ones that
+/// would move further than walking allows, see [`InputValidation::measure_speed`]
+pub fn check_speed(
+    mut players: Query<(
+        Entity,
+        &InputBuffer<ActionState<Inputs>>,
+        &mut InputValidation,
+    )>,
+    server: Single<&LocalTimeline, With<Server>>,
+    policy: Res<ValidationPolicy>,
+    mut flags: MessageWriter<InputFlagged>,
+) {
+    let tick = server.tick();
+    for (player, buffer, mut validation) in &mut players {
+        let Some(end_tick) = buffer.end_tick() else {
+            continue;
+        };
+        let new_ticks = validation
+            .last_input_tick
+            .map_or(1, |last| end_tick - last)
+            .clamp(0, policy.speed_window_ticks as i16);
+        validation.last_input_tick = Some(end_tick);
+
+        let implied: f32 = (0..new_ticks)
+            .filter_map(|back| buffer.get(Tick(end_tick.0.wrapping_sub(back as u16))))
+            .map(|inputs| inputs.0.direction().step(MOVE_SPEED).length())
+            .sum();
+        if let Some(violation) = validation.measure_speed(tick, implied, &policy) {
+            flags.write(InputFlagged { player, violation });
+        }
+    }
+}
+
+/// Escalates against flagged clients: log, then throttle, then kick
+pub fn apply_policy(
+    mut flagged: MessageReader<InputFlagged>,
+    mut players: Query<(&PlayerId, &ControlledBy, &mut InputValidation)>,
+    policy: Res<ValidationPolicy>,
+    mut audit_log: ResMut<AuditLog>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    for flag in flagged.read() {
+        let Ok((id, controlled_by, mut validation)) = players.get_mut(flag.player) else {
+            continue;
+        };
+        let action = validation.strike(now, &policy);
+        match action {
+            Action::Kick => {
+                warn!("Kicking {:?}: {}", id.0, flag.violation);
+                commands.trigger(Disconnect {
+                    entity: controlled_by.owner,
+                });
+            }
+            Action::Throttle => {
+                warn!("Throttling {:?}: {}", id.0, flag.violation);
+                commands.entity(flag.player).insert(Throttled {
+                    until: now + policy.throttle_duration,
+                });
+            }
+            Action::Log => info!("Flagged {:?}: {}", id.0, flag.violation),
+        }
+
+        if let Some(file) = &mut audit_log.file {
+            let since_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            let line = format!(
+                "{} {:?} strikes={} action={action} {}\n",
+                since_epoch.as_millis(),
+                id.0,
+                validation.strikes,
+                flag.violation
+            );
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                error!("Could not write to the audit log: {e}");
+            }
+        }
+    }
+}
+
+pub fn expire_throttles(
+    throttled: Query<(Entity, &Throttled)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, throttled) in &throttled {
+        if time.elapsed() >= throttled.until {
+            commands.entity(entity).remove::<Throttled>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{message::Messages, system::RunSystemOnce};
+
+    fn policy() -> ValidationPolicy {
+        ValidationPolicy {
+            throttle_after: 2,
+            kick_after: 4,
+            forgive_after: Duration::from_secs(10),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn strikes_escalate_from_log_to_throttle_to_kick() {
+        let policy = policy();
+        let mut validation = InputValidation::new(Duration::ZERO);
+        let actions: Vec<_> = (1..=5)
+            .map(|second| validation.strike(Duration::from_secs(second), &policy))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                Action::Log,
+                Action::Throttle,
+                Action::Throttle,
+                Action::Kick,
+                Action::Kick
+            ]
+        );
+    }
+
+    #[test]
+    fn strikes_are_forgiven_after_a_quiet_period() {
+        let policy = policy();
+        let mut validation = InputValidation::new(Duration::ZERO);
+        validation.strike(Duration::from_secs(1), &policy);
+        assert_eq!(
+            validation.strike(Duration::from_secs(2), &policy),
+            Action::Throttle
+        );
+        // exactly `forgive_after` later still counts
+        assert_eq!(
+            validation.strike(Duration::from_secs(12), &policy),
+            Action::Throttle
+        );
+        assert_eq!(
+            validation.strike(Duration::from_secs(23), &policy),
+            Action::Log
+        );
+    }
+
+    fn walk(up: bool, down: bool, left: bool, right: bool) -> Inputs {
+        Inputs::Direction(Direction {
+            up,
+            down,
+            left,
+            right,
+        })
+    }
+
+    fn inputs_world(policy: ValidationPolicy) -> World {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        world.insert_resource(policy);
+        world.init_resource::<Messages<InputFlagged>>();
+        world
+    }
+
+    fn flags(world: &mut World) -> Vec<Violation> {
+        world
+            .resource_mut::<Messages<InputFlagged>>()
+            .drain()
+            .map(|flag| flag.violation)
+            .collect()
+    }
+
+    #[test]
+    fn input_rate_is_flagged_once_per_window() {
+        let mut world = inputs_world(ValidationPolicy {
+            max_input_changes_per_second: 3,
+            ..default()
+        });
+        let player = world
+            .spawn((
+                ActionState(Inputs::default()),
+                InputValidation::new(Duration::ZERO),
+            ))
+            .id();
+
+        let mut violations = Vec::new();
+        for change in 0..6 {
+            world.get_mut::<ActionState<Inputs>>(player).unwrap().0 =
+                walk(change % 2 == 0, false, false, change % 2 == 1);
+            world.run_system_once(check_inputs).unwrap();
+            violations.extend(flags(&mut world));
+        }
+        assert_eq!(violations, [Violation::InputRate { changes: 4 }]);
+
+        // a new window starts counting from zero
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        world.get_mut::<ActionState<Inputs>>(player).unwrap().0 = walk(false, false, true, false);
+        world.run_system_once(check_inputs).unwrap();
+        assert!(flags(&mut world).is_empty());
+    }
+
+    #[test]
+    fn opposing_directions_are_flagged_when_pressed() {
+        let mut world = inputs_world(ValidationPolicy::default());
+        let player = world
+            .spawn((
+                ActionState(walk(true, true, false, false)),
+                InputValidation::new(Duration::ZERO),
+            ))
+            .id();
+        world.run_system_once(check_inputs).unwrap();
+        assert_eq!(flags(&mut world), [Violation::OpposingDirections]);
+
+        // held, not pressed again
+        world.run_system_once(check_inputs).unwrap();
+        assert!(flags(&mut world).is_empty());
+
+        world.get_mut::<ActionState<Inputs>>(player).unwrap().0 = walk(false, false, true, true);
+        world.run_system_once(check_inputs).unwrap();
+        assert_eq!(flags(&mut world), [Violation::OpposingDirections]);
+
+        world.get_mut::<ActionState<Inputs>>(player).unwrap().0 = walk(true, false, true, false);
+        world.run_system_once(check_inputs).unwrap();
+        assert!(flags(&mut world).is_empty());
+    }
+
+    #[test]
+    fn input_timing_bounds() {
+        let policy = ValidationPolicy::default();
+        assert_eq!(timing_violation(Tick(1032), Tick(1000), &policy), None);
+        assert_eq!(
+            timing_violation(Tick(1033), Tick(1000), &policy),
+            Some(Violation::TicksAhead { ticks: 33 })
+        );
+        assert_eq!(timing_violation(Tick(984), Tick(1000), &policy), None);
+        assert_eq!(
+            timing_violation(Tick(983), Tick(1000), &policy),
+            Some(Violation::TicksBehind { ticks: 17 })
+        );
+    }
+
+    #[test]
+    fn input_timing_across_the_tick_wrap() {
+        let policy = ValidationPolicy::default();
+        assert_eq!(timing_violation(Tick(5), Tick(65530), &policy), None);
+        assert_eq!(
+            timing_violation(Tick(40), Tick(65530), &policy),
+            Some(Violation::TicksAhead { ticks: 46 })
+        );
+        assert_eq!(
+            timing_violation(Tick(65500), Tick(10), &policy),
+            Some(Violation::TicksBehind { ticks: 46 })
+        );
+    }
+
+    /// Feeds `inputs_per_tick` walking diagonal inputs on every server tick for `ticks` ticks
+    fn measure(inputs_per_tick: f32, ticks: u16) -> Vec<Violation> {
+        let policy = ValidationPolicy::default();
+        let diagonal = walk(true, false, false, true)
+            .direction()
+            .step(MOVE_SPEED)
+            .length();
+        let mut validation = InputValidation::new(Duration::ZERO);
+        (0..=ticks)
+            .filter_map(|tick| {
+                validation.measure_speed(
+                    Tick(tick.wrapping_add(65000)),
+                    diagonal * inputs_per_tick,
+                    &policy,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walking_is_not_a_speed_anomaly() {
+        assert!(measure(1.0, 640).is_empty());
+        // jittery delivery within the tolerance
+        assert!(measure(1.2, 640).is_empty());
+    }
+
+    #[test]
+    fn inputs_for_more_ticks_than_the_server_ran_are_a_speed_anomaly() {
+        let violations = measure(1.5, 640);
+        assert_eq!(violations.len(), 10);
+        assert!(matches!(
+            violations[0],
+            Violation::SpeedAnomaly { implied, allowed } if implied > allowed
+        ));
+    }
+
+    #[test]
+    fn throttles_expire() {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        let player = world
+            .spawn(Throttled {
+                until: Duration::from_secs(2),
+            })
+            .id();
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        world.run_system_once(expire_throttles).unwrap();
+        assert!(world.entity(player).contains::<Throttled>());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        world.run_system_once(expire_throttles).unwrap();
+        assert!(!world.entity(player).contains::<Throttled>());
+    }
+}
@@ -1,5 +1,6 @@
 use async_compat::Compat;
 use bevy::{
+    app::TerminalCtrlCHandlerPlugin,
     diagnostic::DiagnosticsPlugin,
     ecs::{lifecycle::HookContext, world::DeferredWorld},
     log::{Level, LogPlugin},
@@ -14,12 +15,19 @@ use lightyear::prelude::{
 };
 use lightyear::{netcode::NetcodeServer, prelude::server::NetcodeConfig};
 
+use crate::protocol::{TICK_DURATION, conditioner::NetworkConditions, random::GameRng};
 use serde::{Deserialize, Serialize};
 use std::{
     net::{Ipv4Addr, SocketAddr},
-    time::Duration,
+    path::Path,
 };
 
+/// Optional local settings of the server, in the same format as the client's `client.json`
+pub const SERVER_CONFIG: &str = "./server.json";
+
+/// Port the WebTransport server listens on
+pub const SERVER_PORT: u16 = 5888;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum WebTransportCertificateSettings {
     /// Load certificate pem files from disk
@@ -129,12 +137,36 @@ pub(crate) fn start(mut commands: Commands, server: Single<Entity, With<Server>>
     });
 }
 
+/// Spawns the WebTransport server entity that clients connect to
+pub fn spawn_server(app: &mut App) -> Entity {
+    app.world_mut()
+        .spawn(ExampleServer {
+            transport: ServerTransports::WebTransport {
+                local_port: SERVER_PORT,
+                certificate: WebTransportCertificateSettings::FromFile {
+                    cert: "./certificates/cert.pem".to_string(),
+                    key: "./certificates/key.pem".to_string(),
+                },
+            },
+            shared: SharedSettings {
+                protocol_id: crate::protocol::version::NETCODE_PROTOCOL_ID,
+                private_key: [
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 0, 0,
+                ],
+            },
+        })
+        .id()
+}
+
 pub fn init() {
     println!("init the server");
 
     let mut app = App::new();
     app.add_plugins((
         MinimalPlugins,
+        // Ctrl+C exits cleanly, so that the world snapshot gets written
+        TerminalCtrlCHandlerPlugin,
         LogPlugin {
             level: Level::INFO,
             filter: "wgpu=error,bevy_render=info,bevy_ecs=warn,bevy_time=warn,naga=warn,bevy_enhanced_input::action::fns=error".to_string(),
@@ -142,29 +174,17 @@ pub fn init() {
         },
         StatesPlugin,
         ServerPlugins {
-              tick_duration: Duration::from_secs_f64(1.0 / 64.0),
-          },      DiagnosticsPlugin,
+            tick_duration: TICK_DURATION,
+        },
+        DiagnosticsPlugin,
     ));
 
-    app.world_mut().spawn(ExampleServer {
-        transport: ServerTransports::WebTransport {
-            local_port: 5888,
-            certificate: WebTransportCertificateSettings::FromFile {
-                cert: "./certificates/cert.pem".to_string(),
-                key: "./certificates/key.pem".to_string(),
-            },
-        },
-        shared: SharedSettings {
-            protocol_id: 0,
-            private_key: [
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0,
-            ],
-        },
-    });
+    spawn_server(&mut app);
     app.add_systems(Startup, start);
 
     app.add_plugins(super::server::plugin::ServerPlugin);
+    app.insert_resource(NetworkConditions::load(Path::new(SERVER_CONFIG)));
+    app.insert_resource(GameRng::load(Path::new(SERVER_CONFIG)));
 
     app.run();
 }